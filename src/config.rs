@@ -1,6 +1,13 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use directories::ProjectDirs;
+use livesplit_core::{comparison::personal_best, TimingMethod};
 use serde::{Deserialize, Serialize};
 
+use crate::{layout::Component, wl_split_timer::DEFAULT_BACKUPS};
+
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Config {
     pub anchor: String,
     pub margin: (i32, i32, i32, i32),
@@ -16,6 +23,33 @@ pub struct Config {
     pub font_color_gold: [u8; 4],
     pub font_family: Option<String>,
     pub target_framerate: u16,
+    pub timing_method: TimingMethod,
+    pub show_both_timing_methods: bool,
+    pub comparison: String,
+    pub backups: usize,
+    /// Number of splits displayed at once, 0 shows all of them
+    pub visible_splits: usize,
+    /// Number of splits after the current one that are kept in view
+    pub upcoming_splits: usize,
+    /// LiveSplit layout (.lsl) to display instead of `layout`
+    pub layout_file: Option<String>,
+    /// Reload the splits and the config when they are changed by another program
+    pub watch_files: bool,
+    /// Shortest time in milliseconds between two images written by the image
+    /// display
+    pub image_interval: u64,
+    /// Port of the browser overlay served on localhost, disabled if unset
+    pub overlay_port: Option<u16>,
+    /// Stylesheet served to the overlay after its default style
+    pub overlay_css: Option<String>,
+    /// Port for tools speaking LiveSplit Server's protocol, disabled if unset
+    pub livesplit_server_port: Option<u16>,
+    /// Socket command to run for a key pressed in the terminal display
+    pub terminal_keys: BTreeMap<String, String>,
+    /// Socket command to run for a key pressed anywhere, needs the `hotkeys`
+    /// feature
+    pub hotkeys: BTreeMap<String, String>,
+    pub layout: Vec<Component>,
 }
 
 impl Default for Config {
@@ -35,6 +69,37 @@ impl Default for Config {
             font_color_gold: [255, 255, 255, 0],
             font_family: None,
             target_framerate: 30,
+            timing_method: TimingMethod::RealTime,
+            show_both_timing_methods: false,
+            comparison: personal_best::NAME.to_string(),
+            backups: DEFAULT_BACKUPS,
+            visible_splits: 0,
+            upcoming_splits: 1,
+            layout_file: None,
+            watch_files: false,
+            image_interval: 1000,
+            overlay_port: None,
+            overlay_css: None,
+            livesplit_server_port: None,
+            terminal_keys: [
+                ("split", "space"),
+                ("start", "s"),
+                ("reset", "r"),
+                ("skip", "n"),
+                ("pause", "p"),
+                ("undo", "u"),
+                ("quit", "q"),
+            ]
+            .iter()
+            .map(|(command, key)| (command.to_string(), key.to_string()))
+            .collect(),
+            hotkeys: BTreeMap::new(),
+            layout: Component::default_layout(),
         }
     }
 }
+
+/// Location of the config file, as used by confy
+pub fn path() -> Option<PathBuf> {
+    ProjectDirs::from("rs", "", "wlsplit").map(|dirs| dirs.config_dir().join("wlsplit.toml"))
+}
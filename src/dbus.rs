@@ -0,0 +1,186 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use livesplit_core::TimeSpan;
+use zbus::{blocking::connection, fdo, interface, zvariant::Value};
+
+use crate::{
+    protocol::{self, Request},
+    time_format::TimeFormat,
+    wl_split_timer::WlSplitTimer,
+};
+
+const NAME: &str = "one.jngl.wlsplit";
+const PATH: &str = "/one/jngl/wlsplit";
+const INTERFACE: &str = "one.jngl.wlsplit.Timer";
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+struct Timer {
+    timer: Arc<Mutex<WlSplitTimer>>,
+}
+
+impl Timer {
+    fn execute(&self, command: &str) -> fdo::Result<()> {
+        let request = Request::parse(command).map_err(fdo::Error::Failed)?;
+        match protocol::execute(&self.timer, &request).error {
+            Some(e) => Err(fdo::Error::Failed(e)),
+            None => Ok(()),
+        }
+    }
+
+    fn state(&self) -> State {
+        State::new(&self.timer.lock().unwrap())
+    }
+}
+
+#[interface(name = "one.jngl.wlsplit.Timer")]
+impl Timer {
+    fn start(&self) -> fdo::Result<()> {
+        self.execute("start")
+    }
+
+    fn split(&self) -> fdo::Result<()> {
+        self.execute("split")
+    }
+
+    fn skip(&self) -> fdo::Result<()> {
+        self.execute("skip")
+    }
+
+    fn pause(&self) -> fdo::Result<()> {
+        self.execute("pause")
+    }
+
+    fn reset(&self) -> fdo::Result<()> {
+        self.execute("reset")
+    }
+
+    fn undo(&self) -> fdo::Result<()> {
+        self.execute("undo")
+    }
+
+    #[zbus(property)]
+    fn phase(&self) -> String {
+        self.state().phase
+    }
+
+    /// Empty while no attempt is running
+    #[zbus(property)]
+    fn current_split(&self) -> String {
+        self.state().current_split
+    }
+
+    /// -1 while no attempt is running
+    #[zbus(property)]
+    fn current_split_index(&self) -> i32 {
+        self.state().current_split_index
+    }
+
+    /// Changes all the time while running, so it is not signalled and has to
+    /// be read when needed
+    #[zbus(property(emits_changed_signal = "false"))]
+    fn current_time(&self) -> String {
+        let time = self.timer.lock().unwrap().time();
+        time.map(|time| TimeFormat::for_file().format_time_span(time))
+            .unwrap_or_default()
+    }
+
+    /// Delta of the last split compared to the current comparison, empty if
+    /// there is none
+    #[zbus(property)]
+    fn delta(&self) -> String {
+        self.state().delta
+    }
+}
+
+/// The properties of the interface, D-Bus has no null so missing values are
+/// empty or -1
+#[derive(Default)]
+struct State {
+    phase: String,
+    current_split: String,
+    current_split_index: i32,
+    delta: String,
+}
+
+impl State {
+    fn new(timer: &WlSplitTimer) -> Self {
+        let format = |time: Option<TimeSpan>, format: TimeFormat| {
+            time.map(|time| format.format_time_span(time))
+                .unwrap_or_default()
+        };
+        Self {
+            phase: format!("{:?}", timer.timer().current_phase()),
+            current_split: timer
+                .current_segment()
+                .map(|segment| segment.name().to_string())
+                .unwrap_or_default(),
+            current_split_index: timer
+                .current_segment_index()
+                .map_or(-1, |index| index as i32),
+            delta: format(timer.last_delta(timer.comparison()), TimeFormat::for_diff()),
+        }
+    }
+
+    /// Properties that differ from `last`, by their D-Bus name
+    fn changed(&self, last: &State) -> HashMap<&'static str, Value<'_>> {
+        let mut changed = HashMap::new();
+        if self.phase != last.phase {
+            changed.insert("Phase", Value::from(&self.phase));
+        }
+        if self.current_split != last.current_split {
+            changed.insert("CurrentSplit", Value::from(&self.current_split));
+        }
+        if self.current_split_index != last.current_split_index {
+            changed.insert("CurrentSplitIndex", Value::from(self.current_split_index));
+        }
+        if self.delta != last.delta {
+            changed.insert("Delta", Value::from(&self.delta));
+        }
+        changed
+    }
+}
+
+/// Offers the timer on the session bus: its commands as methods and its state
+/// as properties, which signal their changes except for the current time
+pub fn spawn(timer: Arc<Mutex<WlSplitTimer>>) -> Result<(), Box<dyn Error>> {
+    let connection = connection::Builder::session()?
+        .name(NAME)?
+        .serve_at(
+            PATH,
+            Timer {
+                timer: Arc::clone(&timer),
+            },
+        )?
+        .build()?;
+
+    // The timer changes through every control interface, so its state is
+    // compared against the last one that was announced
+    thread::spawn(move || {
+        let mut last = State::default();
+        loop {
+            thread::sleep(POLL_INTERVAL);
+            let state = State::new(&timer.lock().unwrap());
+            let changed = state.changed(&last);
+            if !changed.is_empty() {
+                let signal = connection.emit_signal(
+                    None::<()>,
+                    PATH,
+                    "org.freedesktop.DBus.Properties",
+                    "PropertiesChanged",
+                    &(INTERFACE, changed, Vec::<&str>::new()),
+                );
+                if let Err(e) = signal {
+                    eprintln!("Unable to signal D-Bus property changes: {}", e);
+                }
+            }
+            last = state;
+        }
+    });
+    Ok(())
+}
@@ -0,0 +1,89 @@
+use std::{
+    error::Error,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    config::Config,
+    display::render::{self, Renderer},
+    layout::{Layout, Line},
+    wl_split_timer::WlSplitTimer,
+    TimerDisplay,
+};
+
+/// Renders the timer offscreen into a PNG file, which is written again
+/// whenever the displayed lines change, at most once per `image_interval`
+pub struct App {
+    timer: Arc<Mutex<WlSplitTimer>>,
+    layout: Layout,
+    renderer: Renderer,
+    width: usize,
+    path: String,
+    interval: Duration,
+    /// Lines in the last written image and when it was written
+    lines: Vec<Line>,
+    written: Option<Instant>,
+}
+
+impl App {
+    pub fn new(
+        timer: WlSplitTimer,
+        layout: Layout,
+        config: &Config,
+        path: String,
+    ) -> Result<Self, Box<dyn Error>> {
+        Ok(Self {
+            timer: Arc::new(Mutex::new(timer)),
+            renderer: Renderer::new(config, layout.palette())?,
+            layout,
+            width: config.width,
+            path,
+            interval: Duration::from_millis(config.image_interval),
+            lines: Vec::new(),
+            written: None,
+        })
+    }
+}
+
+impl TimerDisplay for App {
+    fn run(&mut self) -> Result<bool, Box<dyn Error>> {
+        let timer = self.timer.lock().unwrap();
+        if timer.exit {
+            return Ok(true);
+        }
+        if self
+            .written
+            .is_some_and(|written| written.elapsed() < self.interval)
+        {
+            return Ok(false);
+        }
+        let lines = self.layout.lines(&timer);
+        drop(timer);
+        if lines == self.lines {
+            return Ok(false);
+        }
+
+        let size = (self.width, self.renderer.height(lines.len()));
+        let mut buffer = vec![0; size.0 * size.1 * 4];
+        self.renderer.draw(&mut buffer, size, 1, &lines, &[]);
+        render::write_png(&self.path, size, &render::to_rgba(&buffer))?;
+        self.lines = lines;
+        self.written = Some(Instant::now());
+        Ok(false)
+    }
+
+    fn reload(&mut self, config: &Config, layout: Layout) -> Result<(), Box<dyn Error>> {
+        self.renderer = Renderer::new(config, layout.palette())?;
+        self.layout = layout;
+        self.width = config.width;
+        self.interval = Duration::from_millis(config.image_interval);
+        self.lines.clear();
+        self.written = None;
+        Ok(())
+    }
+
+    fn timer(&self) -> &Arc<Mutex<WlSplitTimer>> {
+        &self.timer
+    }
+}
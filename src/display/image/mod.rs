@@ -0,0 +1,4 @@
+#[allow(clippy::module_inception)]
+mod image;
+
+pub use self::image::App;
@@ -1,3 +1,5 @@
+mod render;
+
 mod terminal;
 
 pub use self::terminal::App as TerminalApp;
@@ -8,4 +10,8 @@ pub use self::null::App as Headless;
 
 mod smithay;
 
-pub use self::smithay::App as Wayland;
\ No newline at end of file
+pub use self::smithay::App as Wayland;
+
+mod image;
+
+pub use self::image::App as ImageApp;
@@ -1,3 +1,4 @@
+#[allow(clippy::module_inception)]
 mod null;
 
-pub use self::null::App;
\ No newline at end of file
+pub use self::null::App;
@@ -1,4 +1,4 @@
-use crate::{wl_split_timer::WlSplitTimer, TimerDisplay};
+use crate::{config::Config, layout::Layout, wl_split_timer::WlSplitTimer, TimerDisplay};
 
 use std::{
     error::Error,
@@ -24,6 +24,10 @@ impl TimerDisplay for App {
         Ok(false)
     }
 
+    fn reload(&mut self, _config: &Config, _layout: Layout) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
     fn timer(&self) -> &Arc<Mutex<WlSplitTimer>> {
         &self.timer
     }
@@ -0,0 +1,470 @@
+use std::{convert::TryInto, error::Error, fs, path::Path};
+
+use andrew::{text::Text, Canvas, Endian};
+use font_kit::{family_name::FamilyName, properties::Properties, source::SystemSource};
+use png::HasParameters;
+
+use crate::{
+    config::Config,
+    layout::{Kind, Line, Palette},
+};
+
+/// Area of a buffer that was drawn to, as x, y, width and height
+pub type Damage = [usize; 4];
+
+/// Draws the lines of a layout into a buffer of pixels, independent of where
+/// the buffer is displayed
+pub struct Renderer {
+    font_data: Vec<u8>,
+    properties: RenderProperties,
+}
+
+impl Renderer {
+    /// Draws in the colors of the palette, usually the one of the layout
+    pub fn new(config: &Config, palette: Palette) -> Result<Self, Box<dyn Error>> {
+        Ok(Self {
+            font_data: load_font(config)?,
+            properties: RenderProperties::new(config, palette),
+        })
+    }
+
+    /// Height needed for the given number of lines, before scaling
+    pub fn height(&self, rows: usize) -> usize {
+        get_total_height(rows, self.properties.text_height, self.properties.padding_v)
+    }
+
+    /// Draws the lines into a buffer of premultiplied ARGB8888 pixels. Only
+    /// rows that changed are drawn again if the buffer still holds the frame
+    /// of the `previous` lines, otherwise these have to be empty.
+    pub fn draw(
+        &self,
+        buffer: &mut [u8],
+        size: (usize, usize),
+        scale: usize,
+        lines: &[Line],
+        previous: &[Line],
+    ) -> Vec<Damage> {
+        let mut pixels = Pixels::new(buffer, size.0, size.1);
+        let full_redraw = lines.len() != previous.len();
+        let mut damage = Vec::new();
+        if full_redraw {
+            damage.push([0, 0, size.0, size.1]);
+            pixels.fill((0, 0), size, self.properties.palette.background);
+        }
+        for (row, line) in lines.iter().enumerate() {
+            if full_redraw || previous[row] != *line {
+                damage.push(self.draw_line(&mut pixels, row, line, size.0, scale));
+            }
+        }
+        damage
+    }
+
+    /// Draws a line of the layout over the whole width of its row
+    fn draw_line(
+        &self,
+        pixels: &mut Pixels,
+        row: usize,
+        line: &Line,
+        width: usize,
+        scale: usize,
+    ) -> Damage {
+        let row_height = (self.properties.text_height + self.properties.padding_v) * scale;
+        let y = self.properties.padding_v * scale + row * row_height;
+        let text_height = (self.properties.text_height * scale) as f32;
+        let padding_h = self.properties.padding_h * scale;
+        let palette = &self.properties.palette;
+        pixels.fill((0, y), (width, row_height), palette.background);
+        if line.kind == Kind::Separator {
+            let thickness = scale.max(1);
+            pixels.fill(
+                (padding_h, y + (row_height - thickness) / 2),
+                (width.saturating_sub(2 * padding_h), thickness),
+                palette.separator,
+            );
+            return [0, y, width, row_height];
+        }
+
+        let text = match line.kind {
+            Kind::Split { current: true } => format!("> {}", line.text),
+            _ => line.text.clone(),
+        };
+        pixels.draw_text(&Text::new(
+            (padding_h, y),
+            palette.text,
+            &self.font_data,
+            text_height,
+            1.0,
+            text,
+        ));
+
+        let value_height = match line.kind {
+            Kind::Timer => text_height * 1.2,
+            _ => text_height,
+        };
+        let mut value = Text::new(
+            (0, y),
+            palette.color(line.color),
+            &self.font_data,
+            value_height,
+            1.0,
+            &line.value,
+        );
+        value.pos.0 = width.saturating_sub(value.get_width() + padding_h);
+        pixels.draw_text(&value);
+
+        if let Some((delta, color)) = &line.delta {
+            let mut text = Text::new(
+                (0, y + self.properties.text_height / 20 * scale),
+                palette.color(*color),
+                &self.font_data,
+                text_height * 0.9,
+                1.0,
+                "-:--:--.---",
+            );
+            // Deltas are left aligned in a column wide enough for any of them
+            text.pos.0 = value.pos.0.saturating_sub(text.get_width() + 3 * padding_h);
+            text.text = delta.clone();
+            pixels.draw_text(&text);
+        }
+        [0, y, width, row_height]
+    }
+}
+
+/// Converts premultiplied ARGB8888 pixels to RGBA with straight alpha
+pub fn to_rgba(buffer: &[u8]) -> Vec<u8> {
+    buffer
+        .chunks_exact(4)
+        .flat_map(|pixel| {
+            let pixel = u32::from_ne_bytes(pixel.try_into().unwrap());
+            let alpha = pixel >> 24;
+            let channel = |shift: u32| match alpha {
+                0 => 0,
+                _ => ((pixel >> shift & 0xff) * 255 + alpha / 2) / alpha,
+            } as u8;
+            vec![channel(16), channel(8), channel(0), alpha as u8]
+        })
+        .collect()
+}
+
+/// Writes 8 bit RGBA pixels as a PNG image. It goes to a temporary file that
+/// is renamed over the target, so readers never see half an image, but unlike
+/// the splits it isn't synced to disk as it is written again all the time.
+pub fn write_png(path: &str, size: (usize, usize), rgba: &[u8]) -> Result<(), Box<dyn Error>> {
+    let path = Path::new(path);
+    let name = path.file_name().ok_or("Invalid file name")?;
+    let temp = path.with_file_name(format!(".{}.tmp", name.to_string_lossy()));
+    let mut data = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut data, size.0 as u32, size.1 as u32);
+        encoder.set(png::ColorType::RGBA).set(png::BitDepth::Eight);
+        encoder.write_header()?.write_image_data(rgba)?;
+    }
+    let result = (|| -> Result<(), Box<dyn Error>> {
+        fs::write(&temp, &data)?;
+        fs::rename(&temp, path)?;
+        Ok(())
+    })();
+    if result.is_err() {
+        fs::remove_file(&temp).ok();
+    }
+    result
+}
+
+#[derive(Debug, Copy, Clone)]
+struct RenderProperties {
+    text_height: usize,
+    padding_h: usize,
+    padding_v: usize,
+    palette: Palette,
+}
+
+impl RenderProperties {
+    fn new(config: &Config, palette: Palette) -> Self {
+        Self {
+            text_height: config.text_size,
+            padding_h: config.padding_h,
+            padding_v: config.padding_v,
+            palette,
+        }
+    }
+}
+
+fn load_font(config: &Config) -> Result<Vec<u8>, Box<dyn Error>> {
+    let family_name = config
+        .font_family
+        .clone()
+        .map_or_else(|| FamilyName::Monospace, FamilyName::Title);
+    let font = SystemSource::new()
+        .select_best_match(&[family_name], &Properties::new())?
+        .load()?;
+    Ok(font
+        .copy_font_data()
+        .ok_or("Unable to read font data")?
+        .to_vec())
+}
+
+fn get_total_height(len: usize, text_height: usize, padding_v: usize) -> usize {
+    // One extra row leaves room for the padding and the larger timer
+    (len + 1) * (text_height + padding_v)
+}
+
+/// Room left of the text for glyphs extending past its position
+const TEXT_MARGIN: usize = 2;
+
+/// A buffer of premultiplied ARGB8888 pixels, as used by wl_shm. Colors are passed in
+/// andrew's `[a, r, g, b]` order with straight alpha.
+struct Pixels<'a> {
+    buffer: &'a mut [u8],
+    width: usize,
+    height: usize,
+}
+
+impl<'a> Pixels<'a> {
+    fn new(buffer: &'a mut [u8], width: usize, height: usize) -> Self {
+        Self {
+            buffer,
+            width,
+            height,
+        }
+    }
+
+    /// Replaces the pixels of a rectangle with a color, keeping its alpha
+    fn fill(&mut self, pos: (usize, usize), size: (usize, usize), color: [u8; 4]) {
+        let pixel = premultiply(color, 255);
+        for y in pos.1..(pos.1 + size.1).min(self.height) {
+            for x in pos.0..(pos.0 + size.0).min(self.width) {
+                self.set(x, y, pixel);
+            }
+        }
+    }
+
+    /// Draws text over the pixels, antialiased edges blend with what is below
+    fn draw_text(&mut self, text: &Text) {
+        // Drawn in white on black, andrew leaves the coverage of the glyphs
+        // in every color channel
+        let width = text.get_width() + 2 * TEXT_MARGIN;
+        let height = (text.v_metrics.ascent - text.v_metrics.descent).ceil() as usize + 1;
+        let mut coverage = vec![0; width * height * 4];
+        let mut canvas = Canvas::new(&mut coverage, width, height, width * 4, Endian::native());
+        canvas.draw(&Text {
+            pos: (TEXT_MARGIN, 0),
+            color: [255; 4],
+            text: text.text.clone(),
+            font: text.font.clone(),
+            scale: text.scale,
+            v_metrics: text.v_metrics,
+        });
+
+        for (i, pixel) in coverage.chunks_exact(4).enumerate() {
+            // The second byte is a color channel in either byte order
+            if pixel[1] == 0 {
+                continue;
+            }
+            if let Some(x) = (text.pos.0 + i % width).checked_sub(TEXT_MARGIN) {
+                self.blend(x, text.pos.1 + i / width, text.color, pixel[1]);
+            }
+        }
+    }
+
+    /// Draws a color over a pixel, with its alpha scaled by `coverage`
+    fn blend(&mut self, x: usize, y: usize, color: [u8; 4], coverage: u8) {
+        if x >= self.width || y >= self.height {
+            return;
+        }
+        let source = premultiply(color, coverage);
+        let destination = self.get(x, y);
+        let inverse = 255 - (source >> 24);
+        let mut pixel = 0;
+        for shift in [0, 8, 16, 24].iter() {
+            let channel = (source >> shift & 0xff) + multiply(destination >> shift & 0xff, inverse);
+            pixel |= channel.min(255) << shift;
+        }
+        self.set(x, y, pixel);
+    }
+
+    fn get(&self, x: usize, y: usize) -> u32 {
+        let i = (y * self.width + x) * 4;
+        u32::from_ne_bytes(self.buffer[i..i + 4].try_into().unwrap())
+    }
+
+    fn set(&mut self, x: usize, y: usize, pixel: u32) {
+        let i = (y * self.width + x) * 4;
+        self.buffer[i..i + 4].copy_from_slice(&pixel.to_ne_bytes());
+    }
+}
+
+fn premultiply(color: [u8; 4], coverage: u8) -> u32 {
+    let alpha = multiply(color[0].into(), coverage.into());
+    let [r, g, b] = [color[1], color[2], color[3]].map(|channel| multiply(channel.into(), alpha));
+    alpha << 24 | r << 16 | g << 8 | b
+}
+
+/// Multiplies two fractions of 255, rounded
+fn multiply(a: u32, b: u32) -> u32 {
+    let product = a * b + 128;
+    (product + (product >> 8)) >> 8
+}
+
+/// Golden images of the standard layouts, `WLSPLIT_UPDATE_GOLDEN=1 cargo test`
+/// writes them again after an intended change to the rendering. They are drawn
+/// with DejaVu Sans Mono, which has to be installed.
+#[cfg(test)]
+mod tests {
+    use std::{
+        env,
+        fs::{self, File},
+        path::{Path, PathBuf},
+        process,
+    };
+
+    use livesplit_core::{TimeSpan, TimingMethod};
+
+    use super::*;
+    use crate::{
+        layout::{Color, Layout},
+        wl_split_timer::{RunMetadata, WlSplitTimer},
+    };
+
+    const FONT: &str = "DejaVu Sans Mono";
+
+    fn config() -> Config {
+        Config {
+            font_family: Some(FONT.to_string()),
+            ..Default::default()
+        }
+    }
+
+    /// A timer in the middle of its second attempt, game time is paused so
+    /// that every time on display is fixed
+    fn timer(name: &str, splits: &[&str]) -> WlSplitTimer {
+        let file = env::temp_dir().join(format!("wlsplit-{}-{}.json", name, process::id()));
+        fs::remove_file(&file).ok();
+        let metadata = RunMetadata {
+            game_name: Some("Golden Game"),
+            category_name: Some("Any%"),
+            splits: Some(splits.to_vec()),
+        };
+        let mut timer = WlSplitTimer::new(file.to_string_lossy().into_owned(), metadata).unwrap();
+        timer.set_backups(0);
+        timer.set_timing_method(TimingMethod::GameTime);
+
+        let attempt = |timer: &mut WlSplitTimer, seconds: &[f64]| {
+            timer.start();
+            timer.pause_game_time();
+            for seconds in seconds {
+                timer.set_game_time(TimeSpan::from_seconds(*seconds));
+                timer.split().unwrap();
+            }
+        };
+        let personal_best: Vec<f64> = (1..=splits.len()).map(|i| i as f64 * 60.0).collect();
+        attempt(&mut timer, &personal_best);
+        // Ahead in the first split with a gold, behind in the next ones
+        attempt(&mut timer, &[55.5, 125.25, 179.0][..splits.len() / 2 + 1]);
+        timer.set_game_time(TimeSpan::from_seconds(190.125));
+
+        fs::remove_file(timer.file()).ok();
+        fs::remove_file(format!("{}.journal", timer.file())).ok();
+        timer
+    }
+
+    fn read_png(path: &Path) -> ((usize, usize), Vec<u8>) {
+        let file = File::open(path)
+            .unwrap_or_else(|e| panic!("{}: {}, see the tests module", path.display(), e));
+        let (info, mut reader) = png::Decoder::new(file).read_info().unwrap();
+        let mut rgba = vec![0; info.buffer_size()];
+        reader.next_frame(&mut rgba).unwrap();
+        ((info.width as usize, info.height as usize), rgba)
+    }
+
+    fn golden_path(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/golden")
+            .join(format!("{}.png", name))
+    }
+
+    /// Draws the layout of the config and compares it to its golden image
+    fn assert_golden(name: &str, config: &Config, timer: &WlSplitTimer) {
+        let mut layout = Layout::new(config).unwrap();
+        let renderer = Renderer::new(config, layout.palette())
+            .unwrap_or_else(|e| panic!("The golden images need the font {}: {}", FONT, e));
+        let lines = layout.lines(timer);
+        let size = (config.width, renderer.height(lines.len()));
+        let mut buffer = vec![0; size.0 * size.1 * 4];
+        renderer.draw(&mut buffer, size, 1, &lines, &[]);
+        let rgba = to_rgba(&buffer);
+
+        let path = golden_path(name);
+        if env::var_os("WLSPLIT_UPDATE_GOLDEN").is_some() {
+            write_png(&path.to_string_lossy(), size, &rgba).unwrap();
+            return;
+        }
+        let (golden_size, golden) = read_png(&path);
+        let differing = rgba
+            .chunks_exact(4)
+            .zip(golden.chunks_exact(4))
+            .filter(|(pixel, golden)| pixel != golden)
+            .count();
+        if golden_size != size || differing > 0 {
+            let actual = env::temp_dir().join(format!("wlsplit-{}.png", name));
+            write_png(&actual.to_string_lossy(), size, &rgba).unwrap();
+            panic!(
+                "{} differs from {} in size ({:?} and {:?}) or {} pixels",
+                actual.display(),
+                path.display(),
+                size,
+                golden_size,
+                differing
+            );
+        }
+    }
+
+    #[test]
+    fn draws_the_default_layout() {
+        let timer = timer(
+            "golden-default",
+            &["Tutorial", "Boss 1", "Castle", "Ending"],
+        );
+        assert_golden("default", &config(), &timer);
+    }
+
+    #[test]
+    fn draws_subsplits() {
+        let splits = ["Tutorial", "-Gate", "-Bridge", "{Castle} Boss", "Ending"];
+        let timer = timer("golden-subsplits", &splits);
+        let config = Config {
+            visible_splits: 5,
+            ..config()
+        };
+        assert_golden("subsplits", &config, &timer);
+    }
+
+    #[test]
+    fn draws_separators_in_the_palette_color() {
+        let config = config();
+        let palette = Palette {
+            separator: [255, 1, 2, 3],
+            ..Palette::from_config(&config)
+        };
+        let renderer = Renderer::new(&config, palette)
+            .unwrap_or_else(|e| panic!("The golden images need the font {}: {}", FONT, e));
+        let line = Line {
+            kind: Kind::Separator,
+            text: String::new(),
+            delta: None,
+            value: String::new(),
+            color: Color::Normal,
+        };
+        let size = (config.width, renderer.height(1));
+        let mut buffer = vec![0; size.0 * size.1 * 4];
+        renderer.draw(&mut buffer, size, 1, &[line], &[]);
+        let rgba = to_rgba(&buffer);
+
+        let pixel = |x: usize, y: usize| &rgba[(y * size.0 + x) * 4..][..4];
+        let y = config.padding_v + (config.text_size + config.padding_v - 1) / 2;
+        assert_eq!(pixel(size.0 / 2, y), [1, 2, 3, 255]);
+        assert_eq!(
+            pixel(size.0 / 2, y + 1),
+            [0, 0, 0, config.background_opacity]
+        );
+    }
+}
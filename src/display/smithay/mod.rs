@@ -1,3 +1,4 @@
+#[allow(clippy::module_inception)]
 mod smithay;
 
-pub use self::smithay::App;
\ No newline at end of file
+pub use self::smithay::App;
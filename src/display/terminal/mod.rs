@@ -1,3 +1,4 @@
+#[allow(clippy::module_inception)]
 mod terminal;
 
-pub use self::terminal::App;
\ No newline at end of file
+pub use self::terminal::App;
@@ -1,15 +1,23 @@
 use crossterm::{
+    event::{self, Event, KeyCode, KeyEvent, KeyModifiers},
     execute,
-    terminal::{EnterAlternateScreen, LeaveAlternateScreen},
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
 
-use crate::{time_format::TimeFormat, wl_split_timer::WlSplitTimer, TimerDisplay};
-use livesplit_core::TimeSpan;
+use crate::{
+    config::Config,
+    layout::{Kind, Layout as ComponentLayout},
+    protocol::{self, Request},
+    wl_split_timer::WlSplitTimer,
+    TimerDisplay,
+};
+use livesplit_core::comparison;
 use std::io::{stdout, Stdout};
 use std::{
-    convert::TryInto,
+    collections::BTreeMap,
     error::Error,
     sync::{Arc, Mutex},
+    time::Duration,
 };
 use tui::{
     backend::CrosstermBackend,
@@ -18,38 +26,81 @@ use tui::{
     widgets::Row,
     widgets::Table,
     widgets::TableState,
-    widgets::{Block, Borders},
+    widgets::{Block, Borders, Paragraph},
     Terminal,
 };
 
 pub struct App {
     timer: Arc<Mutex<WlSplitTimer>>,
     terminal: Terminal<CrosstermBackend<Stdout>>,
+    layout: ComponentLayout,
+    /// Normalized key names and the commands they run
+    keys: Vec<(String, Request)>,
+    /// Error of the last command run with a key, shown below the splits
+    status: Option<String>,
 }
 impl App {
-    pub fn new(timer: WlSplitTimer) -> Self {
+    pub fn new(
+        timer: WlSplitTimer,
+        layout: ComponentLayout,
+        config: &Config,
+    ) -> Result<Self, Box<dyn Error>> {
+        let keys = parse_keys(&config.terminal_keys)?;
         let mut stdout = stdout();
         execute!(stdout, EnterAlternateScreen).unwrap();
+        enable_raw_mode().unwrap();
 
         let backend = CrosstermBackend::new(stdout);
         let mut terminal = Terminal::new(backend).unwrap();
         terminal.hide_cursor().unwrap();
 
-        Self {
+        Ok(Self {
             timer: Arc::new(Mutex::new(timer)),
             terminal,
-        }
+            layout,
+            keys,
+            status: None,
+        })
     }
 
     fn quit(&mut self) {
+        disable_raw_mode().unwrap();
         execute!(stdout(), LeaveAlternateScreen).unwrap();
         self.terminal.show_cursor().unwrap();
     }
+
+    /// Runs the commands bound to the keys pressed since the last frame
+    fn handle_keys(&mut self) -> Result<(), Box<dyn Error>> {
+        while event::poll(Duration::from_millis(0))? {
+            let key = match event::read()? {
+                Event::Key(key) => key,
+                _ => continue,
+            };
+            // Raw mode swallows the interrupt signal
+            if key == KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL) {
+                self.timer.lock().unwrap().quit();
+                continue;
+            }
+            let name = key_name(key);
+            let request = self
+                .keys
+                .iter()
+                .find(|(key, _)| name.as_deref() == Some(key.as_str()))
+                .map(|(_, request)| request);
+            if let Some(request) = request {
+                let response = protocol::execute(&self.timer, request);
+                self.status = response
+                    .error
+                    .map(|error| format!("{}: {}", request.cmd, error));
+            }
+        }
+        Ok(())
+    }
 }
 
 impl TimerDisplay for App {
     fn run(&mut self) -> Result<bool, Box<dyn Error>> {
-        let mut rows: Vec<Vec<String>> = Vec::new();
+        self.handle_keys()?;
 
         let timer = self.timer.lock().unwrap();
         if timer.exit {
@@ -57,79 +108,30 @@ impl TimerDisplay for App {
             self.quit();
             return Ok(true);
         }
-        for (i, segment) in timer.segments().iter().enumerate() {
-            let mut row = Vec::new();
-            let index = timer.current_segment_index().unwrap_or(0);
-
-            // Segment
-            if i == index {
-                row.push(format!("> {}", segment.name().to_string()));
-            } else {
-                row.push(format!("  {}", segment.name().to_string()));
-            }
+        let lines = self.layout.lines(&timer);
+        let comparison = comparison::shorten(timer.comparison()).to_string();
+        drop(timer);
 
-            // Current
-            row.push(match i.cmp(&index) {
-                std::cmp::Ordering::Equal => {
-                    diff_time(timer.time(), segment.personal_best_split_time().real_time)
+        let mut title = String::new();
+        let mut rows: Vec<Vec<String>> = Vec::new();
+        for line in lines {
+            let text = match line.kind {
+                Kind::Title => {
+                    title = format!("{} - {}", line.text, line.value);
+                    continue;
                 }
-                std::cmp::Ordering::Less => diff_time(
-                    segment.split_time().real_time,
-                    timer.segments()[i].personal_best_split_time().real_time,
-                ),
-                _ => "".to_string(),
-            });
-
-            let time = if let Some(time) = segment.personal_best_split_time().real_time {
-                Some(time)
-            } else if segment.segment_history().iter().len() == 0 {
-                segment.split_time().real_time
-            } else {
-                None
+                Kind::Split { current: true } => format!("> {}", line.text),
+                Kind::Split { current: false } => format!("  {}", line.text),
+                Kind::Timer | Kind::Info | Kind::Separator => line.text,
             };
-            row.push(time.map_or("-:--:--.---".to_string(), |time| {
-                TimeFormat::default()
-                    .format_time(time.to_duration().num_milliseconds() as u128, false)
-            }));
-
-            rows.push(row);
+            let delta = line.delta.map(|(delta, _)| delta).unwrap_or_default();
+            rows.push(vec![text, delta, line.value]);
         }
 
-        if let Some(time) = timer.time() {
-            rows.push(vec![
-                "".to_string(),
-                "".to_string(),
-                TimeFormat::default().format_time(
-                    time.to_duration().num_milliseconds().try_into().unwrap(),
-                    false,
-                ),
-            ]);
-        }
-
-        rows.push(vec![
-            "".to_string(),
-            "Sum of best segments".to_string(),
-            TimeFormat::default().format_time(timer.sum_of_best_segments() as u128, false),
-        ]);
-
-        rows.push(vec![
-            "".to_string(),
-            "Best possible time".to_string(),
-            TimeFormat::default().format_time(timer.best_possible_time() as u128, false),
-        ]);
-
-        let title = format!(
-            "{} {} - {}",
-            timer.run().game_name(),
-            timer.run().category_name(),
-            timer.run().attempt_count()
-        );
-
-        drop(timer);
-
+        let status = self.status.clone().unwrap_or_default();
         self.terminal.draw(|f| {
             let rects = Layout::default()
-                .constraints([Constraint::Percentage(0)].as_ref())
+                .constraints([Constraint::Min(0), Constraint::Length(1)].as_ref())
                 .margin(0)
                 .split(f.size());
 
@@ -137,7 +139,7 @@ impl TimerDisplay for App {
                 .fg(Color::Yellow)
                 .add_modifier(Modifier::BOLD);
             let normal_style = Style::default().fg(Color::White);
-            let header = ["Segment", "Current", "Best"];
+            let header = ["Segment", "Current", &comparison];
             let rows = rows.iter().map(|i| Row::StyledData(i.iter(), normal_style));
             let t = Table::new(header.iter(), rows)
                 .block(Block::default().borders(Borders::NONE).title(title))
@@ -149,21 +151,165 @@ impl TimerDisplay for App {
                     Constraint::Percentage(30),
                 ]);
             f.render_stateful_widget(t, rects[0], &mut TableState::default());
+            let status = Paragraph::new(status.as_str()).style(Style::default().fg(Color::Red));
+            f.render_widget(status, rects[1]);
         })?;
         Ok(false)
     }
 
+    fn reload(&mut self, config: &Config, layout: ComponentLayout) -> Result<(), Box<dyn Error>> {
+        self.keys = parse_keys(&config.terminal_keys)?;
+        self.layout = layout;
+        Ok(())
+    }
+
     fn timer(&self) -> &Arc<Mutex<WlSplitTimer>> {
         &self.timer
     }
 }
-fn diff_time(time: Option<TimeSpan>, best: Option<TimeSpan>) -> String {
-    if let (Some(time), Some(best)) = (time, best) {
-        let time = time.to_duration().num_milliseconds();
-        let best = best.to_duration().num_milliseconds();
-        let negative = best > time;
-        let diff = if negative { best - time } else { time - best } as u128;
-        return TimeFormat::for_diff().format_time(diff, negative);
+
+/// Reads the `terminal_keys` table of commands and their keys, a command
+/// that can't be parsed is rejected with the whole table
+fn parse_keys(keys: &BTreeMap<String, String>) -> Result<Vec<(String, Request)>, String> {
+    keys.iter()
+        .map(|(command, key)| {
+            let request = Request::parse(command)
+                .map_err(|e| format!("Invalid command for key {}: {}", key, e))?;
+            Ok((normalize_key(key), request))
+        })
+        .collect()
+}
+
+/// Name of a key as written in the config, e.g. `a`, `space`, `f1` or `ctrl-r`
+fn key_name(key: KeyEvent) -> Option<String> {
+    let name = match key.code {
+        KeyCode::Char(' ') => "space".to_string(),
+        KeyCode::Char(c) => c.to_string(),
+        KeyCode::F(n) => format!("f{}", n),
+        KeyCode::Enter => "enter".to_string(),
+        KeyCode::Backspace => "backspace".to_string(),
+        KeyCode::Tab => "tab".to_string(),
+        KeyCode::Esc => "esc".to_string(),
+        KeyCode::Up => "up".to_string(),
+        KeyCode::Down => "down".to_string(),
+        KeyCode::Left => "left".to_string(),
+        KeyCode::Right => "right".to_string(),
+        KeyCode::Home => "home".to_string(),
+        KeyCode::End => "end".to_string(),
+        KeyCode::PageUp => "pageup".to_string(),
+        KeyCode::PageDown => "pagedown".to_string(),
+        KeyCode::Insert => "insert".to_string(),
+        KeyCode::Delete => "delete".to_string(),
+        _ => return None,
+    };
+    Some(with_modifiers(
+        key.modifiers.contains(KeyModifiers::CONTROL),
+        key.modifiers.contains(KeyModifiers::ALT),
+        &name,
+    ))
+}
+
+/// Single characters are case sensitive, named keys and modifiers are not.
+/// Modifiers can be given in any order.
+fn normalize_key(key: &str) -> String {
+    let (mut ctrl, mut alt) = (false, false);
+    let mut name = key;
+    // The name itself can be `-`
+    while let Some((modifier, rest)) = name.split_once('-').filter(|(_, rest)| !rest.is_empty()) {
+        match modifier.to_lowercase().as_str() {
+            "ctrl" => ctrl = true,
+            "alt" => alt = true,
+            _ => break,
+        }
+        name = rest;
+    }
+    match name.chars().count() {
+        1 => with_modifiers(ctrl, alt, name),
+        _ => with_modifiers(ctrl, alt, &name.to_lowercase()),
+    }
+}
+
+fn with_modifiers(ctrl: bool, alt: bool, name: &str) -> String {
+    let mut key = String::new();
+    if ctrl {
+        key.push_str("ctrl-");
+    }
+    if alt {
+        key.push_str("alt-");
+    }
+    key + name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name(code: KeyCode, modifiers: KeyModifiers) -> Option<String> {
+        key_name(KeyEvent::new(code, modifiers))
+    }
+
+    #[test]
+    fn names_keys() {
+        let none = KeyModifiers::NONE;
+        assert_eq!(name(KeyCode::Char('a'), none).as_deref(), Some("a"));
+        assert_eq!(name(KeyCode::Char(' '), none).as_deref(), Some("space"));
+        assert_eq!(name(KeyCode::F(1), none).as_deref(), Some("f1"));
+        assert_eq!(name(KeyCode::PageDown, none).as_deref(), Some("pagedown"));
+        assert_eq!(name(KeyCode::Null, none), None);
+    }
+
+    #[test]
+    fn names_modifiers() {
+        let ctrl_alt = KeyModifiers::CONTROL | KeyModifiers::ALT;
+        assert_eq!(
+            name(KeyCode::Char('r'), KeyModifiers::CONTROL).as_deref(),
+            Some("ctrl-r")
+        );
+        assert_eq!(
+            name(KeyCode::Enter, ctrl_alt).as_deref(),
+            Some("ctrl-alt-enter")
+        );
+        // Shift is part of the character
+        assert_eq!(
+            name(KeyCode::Char('A'), KeyModifiers::SHIFT).as_deref(),
+            Some("A")
+        );
+        assert_eq!(
+            name(KeyCode::Char('-'), KeyModifiers::CONTROL).as_deref(),
+            Some("ctrl--")
+        );
+    }
+
+    #[test]
+    fn normalizes_configured_keys() {
+        assert_eq!(normalize_key("a"), "a");
+        assert_eq!(normalize_key("A"), "A");
+        assert_eq!(normalize_key("Space"), "space");
+        assert_eq!(normalize_key("F1"), "f1");
+        assert_eq!(normalize_key("Ctrl-R"), "ctrl-R");
+        assert_eq!(normalize_key("alt-ctrl-Enter"), "ctrl-alt-enter");
+        assert_eq!(normalize_key("-"), "-");
+        assert_eq!(normalize_key("ctrl--"), "ctrl--");
+    }
+
+    #[test]
+    fn parses_the_commands_of_keys() {
+        let keys: BTreeMap<String, String> = [("split", "Space"), ("set-comparison Best", "b")]
+            .iter()
+            .map(|(command, key)| (command.to_string(), key.to_string()))
+            .collect();
+        let keys = parse_keys(&keys).unwrap();
+        assert_eq!(keys[0].0, "b");
+        assert_eq!(keys[0].1.args, ["Best"]);
+        assert_eq!(
+            keys[1],
+            ("space".to_string(), Request::parse("split").unwrap())
+        );
+
+        let mut invalid = BTreeMap::new();
+        invalid.insert("{\"cmd\":".to_string(), "x".to_string());
+        assert!(parse_keys(&invalid)
+            .unwrap_err()
+            .starts_with("Invalid command for key x"));
     }
-    "".to_string()
 }
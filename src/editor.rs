@@ -0,0 +1,513 @@
+use std::{
+    error::Error,
+    io::{stdout, Stdout},
+};
+
+use crossterm::{
+    cursor::Show,
+    event::{self, Event, KeyCode, KeyEvent, KeyModifiers},
+    execute,
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use livesplit_core::{run::Editor, Run, Segment, TimeSpan, TimingMethod};
+use tui::{
+    backend::CrosstermBackend,
+    layout::{Constraint, Direction, Layout},
+    style::{Color, Modifier, Style},
+    text::Span,
+    widgets::{Block, Borders, Paragraph, Row, Table, TableState},
+    Terminal,
+};
+
+use crate::{config::Config, time_format::TimeFormat, wl_split_timer::WlSplitTimer};
+
+const COLUMNS: [Field; 4] = [
+    Field::Name,
+    Field::SplitTime,
+    Field::SegmentTime,
+    Field::BestSegmentTime,
+];
+
+const HELP: &str = "enter: edit  g/c: game/category  a/A: add below/above  d: remove  J/K: move  t: timing method  H: clear history  s: save  q: quit";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Field {
+    Name,
+    SplitTime,
+    SegmentTime,
+    BestSegmentTime,
+    Game,
+    Category,
+}
+
+impl Field {
+    fn label(self) -> &'static str {
+        match self {
+            Field::Name => "Segment",
+            Field::SplitTime => "Split time",
+            Field::SegmentTime => "Segment time",
+            Field::BestSegmentTime => "Best segment",
+            Field::Game => "Game",
+            Field::Category => "Category",
+        }
+    }
+}
+
+enum Mode {
+    Normal,
+    Input { field: Field, value: String },
+    ConfirmClearHistory,
+    ConfirmQuit,
+}
+
+/// Interactive editor for the segments and times of a splits file
+struct RunEditor {
+    timer: WlSplitTimer,
+    editor: Editor,
+    selected: usize,
+    column: usize,
+    mode: Mode,
+    status: String,
+    modified: bool,
+    exit: bool,
+}
+
+/// Opens the editor for the run of the given timer, changes are only
+/// written when saving
+pub fn edit(mut timer: WlSplitTimer, config: &Config) -> Result<(), Box<dyn Error>> {
+    timer.set_backups(config.backups);
+    let mut editor =
+        Editor::new(timer.run().clone()).map_err(|_| "At least one segment expected")?;
+    editor.select_timing_method(config.timing_method);
+    let mut run_editor = RunEditor {
+        timer,
+        editor,
+        selected: 0,
+        column: 0,
+        mode: Mode::Normal,
+        status: HELP.to_string(),
+        modified: false,
+        exit: false,
+    };
+
+    let _restore = RestoreTerminal;
+    let mut stdout = stdout();
+    execute!(stdout, EnterAlternateScreen)?;
+    enable_raw_mode()?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(stdout))?;
+    terminal.hide_cursor()?;
+
+    run_editor.run(&mut terminal)
+}
+
+/// Leaves raw mode and the alternate screen when the editor returns, also
+/// when it fails halfway through setting up the terminal
+struct RestoreTerminal;
+
+impl Drop for RestoreTerminal {
+    fn drop(&mut self) {
+        disable_raw_mode().ok();
+        execute!(stdout(), LeaveAlternateScreen, Show).ok();
+    }
+}
+
+impl RunEditor {
+    fn run(
+        &mut self,
+        terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+    ) -> Result<(), Box<dyn Error>> {
+        while !self.exit {
+            self.draw(terminal)?;
+            if let Event::Key(key) = event::read()? {
+                self.handle_key(key);
+            }
+        }
+        Ok(())
+    }
+
+    fn draw(
+        &mut self,
+        terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+    ) -> Result<(), Box<dyn Error>> {
+        let state = self.editor.state();
+        let title = format!(
+            "{} ({}) - {:?}{}",
+            state.game,
+            state.category,
+            state.timing_method,
+            if self.modified { " [modified]" } else { "" }
+        );
+        let column = self.column;
+        let rows: Vec<Vec<String>> = state
+            .segments
+            .into_iter()
+            .enumerate()
+            .map(|(index, segment)| {
+                let mut row = vec![
+                    segment.name,
+                    segment.split_time,
+                    segment.segment_time,
+                    segment.best_segment_time,
+                ];
+                if index == self.selected {
+                    row[column] = format!("[{}]", row[column]);
+                }
+                row
+            })
+            .collect();
+        let status = match &self.mode {
+            Mode::Input { field, value } => {
+                format!("{}: {}_  {}", field.label(), value, self.status)
+            }
+            _ => self.status.clone(),
+        };
+        let mut table_state = TableState::default();
+        table_state.select(Some(self.selected));
+
+        terminal.draw(|f| {
+            let rects = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Min(1), Constraint::Length(1)].as_ref())
+                .margin(0)
+                .split(f.size());
+
+            let selected_style = Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD);
+            let normal_style = Style::default().fg(Color::White);
+            let header = COLUMNS.iter().map(|field| field.label());
+            let rows = rows.iter().map(|i| Row::StyledData(i.iter(), normal_style));
+            let t = Table::new(header, rows)
+                .block(Block::default().borders(Borders::NONE).title(title))
+                .highlight_style(selected_style)
+                .highlight_symbol("> ")
+                .widths(&[
+                    Constraint::Percentage(40),
+                    Constraint::Percentage(20),
+                    Constraint::Percentage(20),
+                    Constraint::Percentage(20),
+                ]);
+            f.render_stateful_widget(t, rects[0], &mut table_state);
+            f.render_widget(Paragraph::new(Span::raw(status)), rects[1]);
+        })?;
+        Ok(())
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) {
+        // Raw mode swallows the interrupt signal
+        if key == KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL) {
+            self.exit = true;
+            return;
+        }
+        match std::mem::replace(&mut self.mode, Mode::Normal) {
+            Mode::Normal => self.handle_command(key.code),
+            Mode::Input { field, mut value } => match key.code {
+                KeyCode::Enter => {
+                    if let Err(e) = self.apply(field, &value) {
+                        self.status = e;
+                        self.mode = Mode::Input { field, value };
+                    }
+                }
+                KeyCode::Esc => self.status = HELP.to_string(),
+                KeyCode::Backspace => {
+                    value.pop();
+                    self.mode = Mode::Input { field, value };
+                }
+                KeyCode::Char(c) => {
+                    value.push(c);
+                    self.mode = Mode::Input { field, value };
+                }
+                _ => self.mode = Mode::Input { field, value },
+            },
+            Mode::ConfirmClearHistory => {
+                if key.code == KeyCode::Char('y') {
+                    self.editor.clear_history();
+                    self.modified = true;
+                    self.status = "History cleared".to_string();
+                } else {
+                    self.status = HELP.to_string();
+                }
+            }
+            Mode::ConfirmQuit => {
+                if key.code == KeyCode::Char('q') {
+                    self.exit = true;
+                } else {
+                    self.status = HELP.to_string();
+                }
+            }
+        }
+    }
+
+    fn handle_command(&mut self, key: KeyCode) {
+        let len = self.editor.run().len();
+        match key {
+            KeyCode::Char('q') | KeyCode::Esc => {
+                if self.modified {
+                    self.status =
+                        "Unsaved changes, press q again to quit without saving".to_string();
+                    self.mode = Mode::ConfirmQuit;
+                } else {
+                    self.exit = true;
+                }
+            }
+            KeyCode::Up | KeyCode::Char('k') => self.select(self.selected.saturating_sub(1)),
+            KeyCode::Down | KeyCode::Char('j') => self.select((self.selected + 1).min(len - 1)),
+            KeyCode::Left | KeyCode::Char('h') => self.column = self.column.saturating_sub(1),
+            KeyCode::Right | KeyCode::Char('l') | KeyCode::Tab => {
+                self.column = (self.column + 1) % COLUMNS.len()
+            }
+            KeyCode::Enter | KeyCode::Char('e') => self.start_input(COLUMNS[self.column]),
+            KeyCode::Char('g') => self.start_input(Field::Game),
+            KeyCode::Char('c') => self.start_input(Field::Category),
+            KeyCode::Char('a') => {
+                self.editor.insert_segment_below();
+                self.inserted(self.selected + 1);
+            }
+            KeyCode::Char('A') => {
+                self.editor.insert_segment_above();
+                self.inserted(self.selected);
+            }
+            KeyCode::Char('d') | KeyCode::Delete => {
+                if self.editor.can_remove_segments() {
+                    self.editor.remove_segments();
+                    self.modified = true;
+                    self.select(self.selected.min(len - 2));
+                } else {
+                    self.status = "The last segment can't be removed".to_string();
+                }
+            }
+            KeyCode::Char('K') if self.editor.can_move_segments_up() => {
+                self.editor.move_segments_up();
+                self.modified = true;
+                self.selected -= 1;
+            }
+            KeyCode::Char('J') if self.editor.can_move_segments_down() => {
+                self.editor.move_segments_down();
+                self.modified = true;
+                self.selected += 1;
+            }
+            KeyCode::Char('t') => {
+                let method = match self.editor.selected_timing_method() {
+                    TimingMethod::RealTime => TimingMethod::GameTime,
+                    TimingMethod::GameTime => TimingMethod::RealTime,
+                };
+                self.editor.select_timing_method(method);
+            }
+            KeyCode::Char('H') => {
+                self.status = "Clear the attempt and segment history? [y/n]".to_string();
+                self.mode = Mode::ConfirmClearHistory;
+            }
+            KeyCode::Char('s') => self.save(),
+            _ => {}
+        }
+    }
+
+    fn select(&mut self, index: usize) {
+        self.selected = index;
+        self.editor.select_only(index);
+    }
+
+    /// New segments are unnamed, so their name is asked for right away
+    fn inserted(&mut self, index: usize) {
+        self.modified = true;
+        self.select(index);
+        self.column = 0;
+        self.start_input(Field::Name);
+    }
+
+    fn start_input(&mut self, field: Field) {
+        let value = match field {
+            Field::Game => self.editor.game_name().to_string(),
+            Field::Category => self.editor.category_name().to_string(),
+            Field::Name => self.editor.active_segment().name().to_string(),
+            Field::SplitTime => format_time(self.editor.active_segment().split_time()),
+            Field::SegmentTime => format_time(self.editor.active_segment().segment_time()),
+            Field::BestSegmentTime => format_time(self.editor.active_segment().best_segment_time()),
+        };
+        self.status = String::new();
+        self.mode = Mode::Input { field, value };
+    }
+
+    fn apply(&mut self, field: Field, value: &str) -> Result<(), String> {
+        if field == Field::SplitTime {
+            self.check_split_time(value)?;
+        }
+        let mut segment = self.editor.active_segment();
+        let time = match field {
+            Field::SplitTime => segment.parse_and_set_split_time(value),
+            Field::SegmentTime => segment.parse_and_set_segment_time(value),
+            Field::BestSegmentTime => segment.parse_and_set_best_segment_time(value),
+            Field::Name => {
+                segment.set_name(value.trim());
+                Ok(())
+            }
+            Field::Game => {
+                self.editor.set_game_name(value.trim());
+                Ok(())
+            }
+            Field::Category => {
+                self.editor.set_category_name(value.trim());
+                Ok(())
+            }
+        };
+        time.map_err(|_| format!("Invalid time \"{}\"", value))?;
+        self.modified = true;
+        self.status = HELP.to_string();
+        Ok(())
+    }
+
+    /// The editor would silently move a split time that is out of order, so
+    /// such an edit is rejected instead
+    fn check_split_time(&self, value: &str) -> Result<(), String> {
+        let time = match value.trim() {
+            "" => return Ok(()),
+            value => value
+                .parse::<TimeSpan>()
+                .map_err(|_| format!("Invalid time \"{}\"", value))?,
+        };
+        let method = self.editor.selected_timing_method();
+        let segments = self.editor.run().segments();
+        let split_time = |segment: &Segment| segment.personal_best_split_time()[method];
+        if let Some(previous) = segments[..self.selected].iter().rev().find_map(split_time) {
+            if time < previous {
+                return Err("Split time is before the previous split".to_string());
+            }
+        }
+        if let Some(next) = segments[self.selected + 1..].iter().find_map(split_time) {
+            if time > next {
+                return Err("Split time is after the next split".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    fn save(&mut self) {
+        let run = self.editor.run().clone();
+        self.status = match validate(&run).and_then(|_| self.timer.save_run(run)) {
+            Ok(()) => {
+                self.modified = false;
+                "Saved".to_string()
+            }
+            Err(e) => e,
+        };
+    }
+}
+
+/// Checks for mistakes the editor itself allows. A best segment can't be
+/// slower than the segment in the personal best, unless the split before it
+/// was skipped there.
+fn validate(run: &Run) -> Result<(), String> {
+    for (index, segment) in run.segments().iter().enumerate() {
+        if segment.name().trim().is_empty() {
+            return Err(format!("Segment {} has no name", index + 1));
+        }
+    }
+    for method in [TimingMethod::RealTime, TimingMethod::GameTime] {
+        let mut previous = Some(TimeSpan::zero());
+        for (index, segment) in run.segments().iter().enumerate() {
+            let split_time = segment.personal_best_split_time()[method];
+            let best = segment.best_segment_time()[method];
+            if let (Some(previous), Some(split_time), Some(best)) = (previous, split_time, best) {
+                if best > split_time - previous {
+                    return Err(format!(
+                        "Best segment of segment {} is slower than its personal best",
+                        index + 1
+                    ));
+                }
+            }
+            previous = split_time;
+        }
+    }
+    Ok(())
+}
+
+fn format_time(time: Option<TimeSpan>) -> String {
+    time.map(|time| TimeFormat::for_file().format_time_span(time))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use livesplit_core::Time;
+
+    use super::*;
+    use crate::wl_split_timer::tests::{remove, timer};
+
+    fn time(seconds: f64) -> Time {
+        Time::new().with_real_time(Some(TimeSpan::from_seconds(seconds)))
+    }
+
+    /// The segments a, b and c with the given personal best and best segments
+    fn run(personal_best: [Time; 3], best_segments: [Time; 3]) -> Run {
+        let mut run = Run::new();
+        for (name, (split_time, best)) in ["a", "b", "c"]
+            .iter()
+            .zip(personal_best.iter().zip(best_segments.iter()))
+        {
+            let mut segment = Segment::new(*name);
+            segment.set_personal_best_split_time(*split_time);
+            segment.set_best_segment_time(*best);
+            run.push_segment(segment);
+        }
+        run
+    }
+
+    #[test]
+    fn rejects_split_times_out_of_order() {
+        let run = run([time(1.0), time(2.0), time(3.0)], Default::default());
+        let mut editor = RunEditor {
+            timer: timer("editor-order"),
+            editor: Editor::new(run).unwrap(),
+            selected: 1,
+            column: 0,
+            mode: Mode::Normal,
+            status: String::new(),
+            modified: false,
+            exit: false,
+        };
+        assert!(editor.check_split_time("00:00:02.500").is_ok());
+        assert!(editor.check_split_time(" ").is_ok());
+        assert_eq!(
+            editor.check_split_time("00:00:00.500"),
+            Err("Split time is before the previous split".to_string())
+        );
+        assert_eq!(
+            editor.check_split_time("00:00:03.500"),
+            Err("Split time is after the next split".to_string())
+        );
+        assert_eq!(
+            editor.check_split_time("soon"),
+            Err("Invalid time \"soon\"".to_string())
+        );
+        // Splits without a time are passed over
+        editor.selected = 0;
+        editor.editor.select_only(1);
+        editor.editor.active_segment().set_split_time(None);
+        assert!(editor.check_split_time("00:00:02.500").is_ok());
+        remove(&editor.timer);
+    }
+
+    #[test]
+    fn rejects_unnamed_segments() {
+        let mut run = run(Default::default(), Default::default());
+        assert_eq!(validate(&run), Ok(()));
+        run.segment_mut(1).set_name(" ");
+        assert_eq!(validate(&run), Err("Segment 2 has no name".to_string()));
+    }
+
+    #[test]
+    fn rejects_best_segments_slower_than_the_personal_best() {
+        let personal_best = [time(1.0), time(2.0), time(3.0)];
+        let best = [time(1.0), time(0.5), time(1.0)];
+        assert_eq!(validate(&run(personal_best, best)), Ok(()));
+
+        let best = [time(1.0), time(1.5), time(1.0)];
+        assert_eq!(
+            validate(&run(personal_best, best)),
+            Err("Best segment of segment 2 is slower than its personal best".to_string())
+        );
+        // The personal best of b holds the time of a and b if a was skipped
+        let personal_best = [Time::default(), time(2.0), time(3.0)];
+        assert_eq!(validate(&run(personal_best, best)), Ok(()));
+    }
+}
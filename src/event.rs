@@ -0,0 +1,38 @@
+use serde::{Deserialize, Serialize};
+
+/// Timer changes sent to socket subscribers, see `WlSplitTimer::subscribe`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum Event {
+    Started,
+    Split {
+        index: usize,
+        segment: String,
+        time: Option<String>,
+        delta: Option<String>,
+    },
+    Gold {
+        index: usize,
+        segment: String,
+        segment_time: Option<String>,
+    },
+    Skipped {
+        index: usize,
+        segment: String,
+    },
+    Undo {
+        index: usize,
+        segment: String,
+    },
+    Redo,
+    Paused,
+    Resumed,
+    GameTimePaused,
+    GameTimeResumed,
+    /// Game time set or loading times changed, by an autosplitter for example
+    GameTimeSet,
+    Reset,
+    PersonalBest {
+        time: Option<String>,
+    },
+}
@@ -1,16 +1,41 @@
-use std::{error::Error, fs::File, io::Read, io::Write};
+use std::{
+    convert::TryFrom,
+    error::Error,
+    fs::{self, File},
+    io::Read,
+    io::{BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
 
-use livesplit_core::Run as LivesplitRun;
-use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use chrono::Local;
+
+use livesplit_core::{
+    comparison::personal_best,
+    run::{parser::livesplit as lss_parser, saver::livesplit as lss_saver},
+    Run as LivesplitRun, TimeSpan, TimingMethod,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 
 use crate::time_format::TimeFormat;
 
+/// Version of the JSON splits format, a change to it needs a new entry in
+/// `MIGRATIONS` upgrading files from the previous version
+pub const FORMAT_VERSION: u32 = 1;
+
+type Migration = fn(&mut Value) -> Result<(), String>;
+
+/// Upgrades a file from the version at its index to the next version
+const MIGRATIONS: [Migration; 1] = [migrate_unversioned];
+
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
 pub struct Run {
+    pub format_version: u32,
     pub game_name: String,
     pub category_name: String,
     pub attempt_count: usize,
     pub attempt_history: Vec<Attempt>,
+    pub custom_comparisons: Vec<String>,
     pub segments: Vec<Segment>,
 }
 
@@ -22,10 +47,12 @@ impl Default for Run {
         }];
 
         Self {
+            format_version: FORMAT_VERSION,
             game_name: "Example Splits".to_string(),
             category_name: "Any%".to_string(),
             attempt_count: 0,
             attempt_history: Vec::new(),
+            custom_comparisons: Vec::new(),
             segments,
         }
     }
@@ -35,57 +62,68 @@ impl Run {
     pub fn new(run: &LivesplitRun) -> Self {
         let mut attempt_history: Vec<Attempt> = Vec::new();
         for attempt in run.attempt_history() {
-            if let Some(time) = attempt.time().real_time {
-                attempt_history.push(Attempt {
-                    time: Some(
-                        TimeFormat::for_file()
-                            .format_time(time.total_milliseconds() as u128, false),
-                    ),
-                    id: attempt.index(),
-                    started: attempt.started().map(|t| t.time.to_rfc3339()),
-                    ended: attempt.ended().map(|t| t.time.to_rfc3339()),
-                    pause_time: attempt.pause_time().map(|t| {
-                        TimeFormat::for_file().format_time(t.total_milliseconds() as u128, false)
-                    }),
-                });
-            }
+            attempt_history.push(Attempt {
+                time: attempt.time().real_time.map(format_time),
+                game_time: attempt.time().game_time.map(format_time),
+                id: attempt.index(),
+                started: attempt.started().map(|t| t.time.to_rfc3339()),
+                ended: attempt.ended().map(|t| t.time.to_rfc3339()),
+                pause_time: attempt.pause_time().map(format_time),
+            });
         }
 
+        let custom_comparisons: Vec<String> = run
+            .custom_comparisons()
+            .iter()
+            .filter(|comparison| comparison.as_str() != personal_best::NAME)
+            .cloned()
+            .collect();
+
         let mut segments: Vec<Segment> = Vec::new();
         for segment in run.segments() {
-            let best_segment_time = segment.best_segment_time().real_time.map(|time| {
-                TimeFormat::for_file().format_time(time.total_milliseconds() as u128, false)
-            });
-
-            let personal_best_split_time =
-                segment.personal_best_split_time().real_time.map(|time| {
-                    TimeFormat::for_file().format_time(time.total_milliseconds() as u128, false)
-                });
+            let best_segment_time = segment.best_segment_time();
+            let personal_best_split_time = segment.personal_best_split_time();
 
             let segment_history: Vec<SplitTime> = segment
                 .segment_history()
                 .iter()
                 .map(|entry| SplitTime {
                     id: Some(entry.0),
-                    time: entry.1.real_time.map(|time| {
-                        TimeFormat::for_file().format_time(time.total_milliseconds() as u128, false)
-                    }),
+                    time: entry.1.real_time.map(format_time),
+                    game_time: entry.1.game_time.map(format_time),
+                })
+                .collect();
+
+            let comparisons: Vec<ComparisonTime> = custom_comparisons
+                .iter()
+                .map(|name| {
+                    let time = segment.comparison(name);
+                    ComparisonTime {
+                        name: name.clone(),
+                        time: time.real_time.map(format_time),
+                        game_time: time.game_time.map(format_time),
+                    }
                 })
                 .collect();
 
             segments.push(Segment {
                 name: segment.name().to_string(),
                 segment_history,
-                personal_best_split_time,
-                best_segment_time,
+                personal_best_split_time: personal_best_split_time.real_time.map(format_time),
+                personal_best_split_game_time: personal_best_split_time.game_time.map(format_time),
+                best_segment_time: best_segment_time.real_time.map(format_time),
+                best_segment_game_time: best_segment_time.game_time.map(format_time),
+                comparisons,
             });
         }
 
         Self {
+            format_version: FORMAT_VERSION,
             game_name: run.game_name().to_string(),
             category_name: run.category_name().to_string(),
             attempt_count: run.attempt_count() as usize,
             attempt_history,
+            custom_comparisons,
             segments,
         }
     }
@@ -118,12 +156,14 @@ pub struct Attempt {
     pub started: Option<String>,
     pub ended: Option<String>,
     pub time: Option<String>,
+    pub game_time: Option<String>,
     pub pause_time: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
 pub struct SplitTime {
     pub time: Option<String>,
+    pub game_time: Option<String>,
     pub id: Option<i32>,
 }
 
@@ -131,23 +171,446 @@ pub struct SplitTime {
 pub struct Segment {
     pub name: String,
     pub personal_best_split_time: Option<String>,
+    pub personal_best_split_game_time: Option<String>,
     pub best_segment_time: Option<String>,
+    pub best_segment_game_time: Option<String>,
     pub segment_history: Vec<SplitTime>,
+    pub comparisons: Vec<ComparisonTime>,
 }
 
-pub fn read_json<T: DeserializeOwned>(path: &str) -> Result<T, Box<dyn Error>> {
-    let mut file = File::open(path)?;
-    let mut content = String::new();
-    file.read_to_string(&mut content)?;
-    let result: T = serde_json::from_str(&content)?;
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct ComparisonTime {
+    pub name: String,
+    pub time: Option<String>,
+    pub game_time: Option<String>,
+}
 
-    Ok(result)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FileFormat {
+    Json,
+    Lss,
+}
+
+impl FileFormat {
+    /// Format to write a file in, based on the file extension
+    pub fn from_path(path: &str) -> Self {
+        match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("lss") => FileFormat::Lss,
+            _ => FileFormat::Json,
+        }
+    }
+
+    /// Format of an existing file, falls back to sniffing the content for
+    /// LiveSplit files without the .lss extension
+    pub fn detect(path: &str) -> Self {
+        if FileFormat::from_path(path) == FileFormat::Lss {
+            return FileFormat::Lss;
+        }
+        let mut content = Vec::new();
+        File::open(path)
+            .and_then(|file| file.take(64).read_to_end(&mut content))
+            .ok();
+        let content = content.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(&content);
+        if content.trim_ascii_start().starts_with(b"<") {
+            FileFormat::Lss
+        } else {
+            FileFormat::Json
+        }
+    }
+}
+
+pub fn read_lss(path: &str) -> Result<LivesplitRun, Box<dyn Error>> {
+    let file = BufReader::new(File::open(path)?);
+    Ok(lss_parser::parse(file, Some(PathBuf::from(path)))?)
+}
+
+pub fn write_lss(path: &str, run: &LivesplitRun) -> Result<(), Box<dyn Error>> {
+    write_atomic(path, |file| {
+        lss_saver::save_run(run, file)
+            .map_err(|e| format!("Unable to save LiveSplit file: {:?}", e))?;
+        Ok(())
+    })
+}
+
+/// Flattens the attempt history into one CSV row per attempt and segment,
+/// segments an attempt didn't reach have empty times
+pub fn write_csv(
+    path: &str,
+    run: &LivesplitRun,
+    method: TimingMethod,
+    complete_only: bool,
+) -> Result<(), Box<dyn Error>> {
+    let format_time = |time: Option<TimeSpan>| {
+        time.map(|time| TimeFormat::for_file().format_time_span(time))
+            .unwrap_or_default()
+    };
+    write_atomic(path, |file| {
+        writeln!(
+            file,
+            "attempt,started,ended,pause_time,attempt_time,segment_index,segment,split_time,segment_time"
+        )?;
+        for attempt in run.attempt_history() {
+            let attempt_time = attempt.time()[method];
+            if complete_only && attempt_time.is_none() {
+                continue;
+            }
+            let started = attempt.started().map(|date| date.time.to_rfc3339());
+            let ended = attempt.ended().map(|date| date.time.to_rfc3339());
+            // Skipped segments have no time, the next segment's time includes them
+            let mut split_time = Some(TimeSpan::zero());
+            for (index, segment) in run.segments().iter().enumerate() {
+                let history = segment.segment_history().get(attempt.index());
+                let segment_time = history.and_then(|time| time[method]);
+                split_time = match (history, segment_time) {
+                    (None, _) => None,
+                    (Some(_), Some(time)) => split_time.map(|split_time| split_time + time),
+                    (Some(_), None) => split_time,
+                };
+                let row = [
+                    attempt.index().to_string(),
+                    started.clone().unwrap_or_default(),
+                    ended.clone().unwrap_or_default(),
+                    format_time(attempt.pause_time()),
+                    format_time(attempt_time),
+                    index.to_string(),
+                    segment.name().to_string(),
+                    format_time(split_time.filter(|_| segment_time.is_some())),
+                    format_time(segment_time),
+                ];
+                let row: Vec<String> = row.iter().map(|field| csv_field(field)).collect();
+                writeln!(file, "{}", row.join(","))?;
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Quotes a field if it contains a separator, quote or line break
+fn csv_field(field: &str) -> String {
+    if field.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Reads a JSON splits file, upgrading files written in an older format.
+/// Errors name the offending field, and its line if no upgrade was needed.
+pub fn read_run(path: &str) -> Result<Run, Box<dyn Error>> {
+    let content = fs::read_to_string(path)?;
+    let mut run: Value = serde_json::from_str(&content)?;
+    let version = match run.get("format_version") {
+        Some(version) => version
+            .as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .ok_or("format_version: expected a positive number")?,
+        None => 0,
+    };
+    if version > FORMAT_VERSION {
+        return Err(format!(
+            "format_version {} is newer than the supported version {}, the file was written by a newer wlsplit",
+            version, FORMAT_VERSION
+        )
+        .into());
+    }
+    if version == FORMAT_VERSION {
+        let deserializer = &mut serde_json::Deserializer::from_str(&content);
+        return Ok(serde_path_to_error::deserialize(deserializer)?);
+    }
+
+    for (version, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        migration(&mut run)
+            .map_err(|e| format!("Unable to upgrade from format_version {}: {}", version, e))?;
+    }
+    run["format_version"] = json!(FORMAT_VERSION);
+    serde_path_to_error::deserialize(run).map_err(|e| {
+        format!(
+            "{} (no line available, the file was upgraded from format_version {})",
+            e, version
+        )
+        .into()
+    })
+}
+
+/// Files from before the format was versioned, these can be missing the
+/// comparisons added later on
+fn migrate_unversioned(run: &mut Value) -> Result<(), String> {
+    let run = run.as_object_mut().ok_or("expected an object")?;
+    run.entry("custom_comparisons").or_insert_with(|| json!([]));
+    if let Some(Value::Array(segments)) = run.get_mut("segments") {
+        for segment in segments.iter_mut().filter_map(Value::as_object_mut) {
+            segment.entry("comparisons").or_insert_with(|| json!([]));
+        }
+    }
+    Ok(())
 }
 
 pub fn write_json<T: Serialize>(path: &str, data: T) -> Result<(), Box<dyn Error>> {
     let serialized = serde_json::to_string_pretty(&data)?;
-    let mut file = File::create(path)?;
-    file.write_all(serialized.as_bytes())?;
+    write_atomic(path, |file| Ok(file.write_all(serialized.as_bytes())?))
+}
+
+/// Writes to a temporary file next to the target and renames it over the
+/// target, so a crash or a full disk never leaves a truncated file behind.
+/// A symlink is followed and the file it points to keeps its permissions.
+fn write_atomic<F>(path: &str, write: F) -> Result<(), Box<dyn Error>>
+where
+    F: FnOnce(&mut BufWriter<File>) -> Result<(), Box<dyn Error>>,
+{
+    let path = fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path));
+    let permissions = fs::metadata(&path).map(|metadata| metadata.permissions());
+    let dir = parent_dir(&path);
+    let name = path.file_name().ok_or("Invalid file name")?;
+    let temp = dir.join(format!(".{}.tmp", name.to_string_lossy()));
+
+    let result = (|| -> Result<(), Box<dyn Error>> {
+        let file = File::create(&temp)?;
+        if let Ok(permissions) = permissions {
+            file.set_permissions(permissions)?;
+        }
+        let mut file = BufWriter::new(file);
+        write(&mut file)?;
+        file.flush()?;
+        file.get_ref().sync_all()?;
+        fs::rename(&temp, &path)?;
+        File::open(dir)?.sync_all()?;
+        Ok(())
+    })();
+    if result.is_err() {
+        fs::remove_file(&temp).ok();
+    }
+    result
+}
 
+/// Copies the current version of a file to `<file>.<timestamp>.bak` and
+/// removes the oldest backups so that at most `keep` of them are left, not
+/// counting the backup named `spare`.
+pub fn backup(path: &str, keep: usize, spare: Option<&str>) -> Result<(), Box<dyn Error>> {
+    if keep == 0 || !Path::new(path).exists() {
+        return Ok(());
+    }
+    let backup = format!("{}.{}.bak", path, Local::now().format("%Y%m%d-%H%M%S%.3f"));
+    fs::copy(path, backup)?;
+    let backups = list_backups(path);
+    let old = backups.iter().filter(|name| Some(name.as_str()) != spare);
+    for old in old.skip(keep) {
+        fs::remove_file(backup_path(path, old))?;
+    }
     Ok(())
 }
+
+/// File names of the backups of a file, newest first
+pub fn list_backups(path: &str) -> Vec<String> {
+    let path = Path::new(path);
+    let prefix = match path.file_name() {
+        Some(name) => format!("{}.", name.to_string_lossy()),
+        None => return Vec::new(),
+    };
+    let mut backups: Vec<String> = fs::read_dir(parent_dir(path))
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|entry| entry.file_name().to_string_lossy().to_string())
+                .filter(|name| {
+                    name.strip_prefix(&prefix)
+                        .and_then(|name| name.strip_suffix(".bak"))
+                        .is_some_and(|timestamp| {
+                            timestamp
+                                .chars()
+                                .all(|c| c.is_ascii_digit() || c == '-' || c == '.')
+                        })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    backups.sort_unstable_by(|a, b| b.cmp(a));
+    backups
+}
+
+/// Path of a backup listed by `list_backups`
+pub fn backup_path(path: &str, backup: &str) -> PathBuf {
+    parent_dir(Path::new(path)).join(backup)
+}
+
+fn parent_dir(path: &Path) -> &Path {
+    path.parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."))
+}
+
+fn format_time(time: TimeSpan) -> String {
+    TimeFormat::for_file().format_time(time.total_milliseconds() as u128, false)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env,
+        os::unix::fs::{symlink, PermissionsExt},
+        process,
+    };
+
+    use livesplit_core::Time;
+
+    use super::*;
+
+    /// An empty directory for a test, `name` keeps tests running in parallel apart
+    fn directory(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("wlsplit-{}-{}", name, process::id()));
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn writes_through_symlinks_keeping_permissions() {
+        let dir = directory("write-atomic");
+        let target = dir.join("target.json");
+        let link = dir.join("link.json");
+        fs::write(&target, "old").unwrap();
+        fs::set_permissions(&target, fs::Permissions::from_mode(0o600)).unwrap();
+        symlink(&target, &link).unwrap();
+
+        write_json(&link.to_string_lossy(), "new").unwrap();
+        assert!(fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(fs::read_to_string(&target).unwrap(), "\"new\"");
+        let mode = fs::metadata(&target).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn reads_current_files() {
+        let dir = directory("read-current");
+        let file = dir.join("splits.json");
+        let file = file.to_string_lossy();
+        write_json(&file, Run::default()).unwrap();
+        assert_eq!(read_run(&file).unwrap(), Run::default());
+
+        fs::write(
+            file.as_ref(),
+            "{\n  \"format_version\": 1,\n  \"game_name\": 3\n}",
+        )
+        .unwrap();
+        let error = read_run(&file).unwrap_err().to_string();
+        assert!(error.starts_with("game_name: invalid type"), "{}", error);
+        assert!(error.contains("line 3"), "{}", error);
+        fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn upgrades_unversioned_files() {
+        let dir = directory("read-unversioned");
+        let file = dir.join("splits.json");
+        let file = file.to_string_lossy();
+        let mut unversioned = serde_json::to_value(Run::default()).unwrap();
+        let object = unversioned.as_object_mut().unwrap();
+        object.remove("format_version");
+        object.remove("custom_comparisons");
+        object["segments"][0]
+            .as_object_mut()
+            .unwrap()
+            .remove("comparisons");
+        fs::write(file.as_ref(), unversioned.to_string()).unwrap();
+        assert_eq!(read_run(&file).unwrap(), Run::default());
+
+        unversioned["game_name"] = json!(3);
+        fs::write(file.as_ref(), unversioned.to_string()).unwrap();
+        let error = read_run(&file).unwrap_err().to_string();
+        assert!(error.starts_with("game_name: invalid type"), "{}", error);
+        assert!(
+            error.contains("upgraded from format_version 0"),
+            "{}",
+            error
+        );
+        fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn rejects_newer_files() {
+        let dir = directory("read-newer");
+        let file = dir.join("splits.json");
+        let file = file.to_string_lossy();
+        let newer = FORMAT_VERSION + 1;
+        fs::write(file.as_ref(), format!("{{\"format_version\": {}}}", newer)).unwrap();
+        let error = read_run(&file).unwrap_err().to_string();
+        assert!(error.contains("newer wlsplit"), "{}", error);
+        fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn rotates_backups_sparing_one() {
+        let dir = directory("backup");
+        let file = dir.join("splits.json");
+        let file = file.to_string_lossy();
+        fs::write(file.as_ref(), "splits").unwrap();
+        for timestamp in ["20200101-000000.000", "20200102-000000.000"] {
+            fs::write(format!("{}.{}.bak", file, timestamp), "old").unwrap();
+        }
+        let oldest = "splits.json.20200101-000000.000.bak";
+
+        backup(&file, 2, Some(oldest)).unwrap();
+        let backups = list_backups(&file);
+        assert_eq!(backups.len(), 3);
+        assert_eq!(backups[1], "splits.json.20200102-000000.000.bak");
+        assert_eq!(backups[2], oldest);
+
+        backup(&file, 2, None).unwrap();
+        let backups = list_backups(&file);
+        assert_eq!(backups.len(), 2);
+        assert!(!backups.iter().any(|name| name == oldest));
+        fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn quotes_csv_fields() {
+        assert_eq!(csv_field("Boss 1"), "Boss 1");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+        assert_eq!(csv_field(""), "");
+    }
+
+    #[test]
+    fn writes_a_row_per_attempt_and_segment() {
+        let dir = directory("csv");
+        let file = dir.join("history.csv");
+        let mut run = LivesplitRun::new();
+        for name in ["a", "b,c"] {
+            run.push_segment(livesplit_core::Segment::new(name));
+        }
+        let time = |seconds| Time::new().with_real_time(Some(TimeSpan::from_seconds(seconds)));
+        run.add_attempt_with_index(time(3.0), 1, None, None, None);
+        run.add_attempt_with_index(Time::default(), 2, None, None, None);
+        run.segment_mut(0)
+            .segment_history_mut()
+            .insert(1, time(1.0));
+        run.segment_mut(1)
+            .segment_history_mut()
+            .insert(1, time(2.0));
+        run.segment_mut(0)
+            .segment_history_mut()
+            .insert(2, Time::default());
+
+        let path = file.to_string_lossy();
+        write_csv(&path, &run, TimingMethod::RealTime, false).unwrap();
+        let csv = fs::read_to_string(&file).unwrap();
+        let rows: Vec<&str> = csv.lines().skip(1).collect();
+        assert_eq!(
+            rows,
+            [
+                "1,,,,00:00:03.000,0,a,00:00:01.000,00:00:01.000",
+                "1,,,,00:00:03.000,1,\"b,c\",00:00:03.000,00:00:02.000",
+                "2,,,,,0,a,,",
+                "2,,,,,1,\"b,c\",,",
+            ]
+        );
+
+        write_csv(&path, &run, TimingMethod::RealTime, true).unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap().lines().count(), 3);
+        fs::remove_dir_all(dir).ok();
+    }
+}
@@ -0,0 +1,301 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    error::Error,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use evdev::{Device, EventSummary, KeyCode};
+use inotify::{Inotify, WatchMask};
+
+use crate::{
+    protocol::{self, Request},
+    wl_split_timer::WlSplitTimer,
+};
+
+const INPUT_DIRECTORY: &str = "/dev/input";
+
+/// Modifiers by their prefix in the config, either key of a pair counts
+const MODIFIERS: [(&str, [KeyCode; 2]); 4] = [
+    ("ctrl", [KeyCode::KEY_LEFTCTRL, KeyCode::KEY_RIGHTCTRL]),
+    ("alt", [KeyCode::KEY_LEFTALT, KeyCode::KEY_RIGHTALT]),
+    ("shift", [KeyCode::KEY_LEFTSHIFT, KeyCode::KEY_RIGHTSHIFT]),
+    ("super", [KeyCode::KEY_LEFTMETA, KeyCode::KEY_RIGHTMETA]),
+];
+
+/// A key combination and the socket command it runs
+struct Hotkey {
+    key: KeyCode,
+    modifiers: [bool; 4],
+    command: String,
+}
+
+impl Hotkey {
+    /// Keys are named like in `terminal_keys`, e.g. `f1`, `ctrl-r` or `ctrl--`
+    /// for the minus key, or by their evdev name such as `KEY_PLAYPAUSE` or
+    /// `BTN_0`
+    fn parse(command: &str, key: &str) -> Result<Self, String> {
+        Request::parse(command)?;
+        let (prefix, name) = match key.strip_suffix("--") {
+            Some(prefix) => (prefix, "-"),
+            None if key == "-" => ("", key),
+            None => key.rsplit_once('-').unwrap_or(("", key)),
+        };
+        let mut modifiers = [false; 4];
+        for part in prefix.split('-').filter(|_| !prefix.is_empty()) {
+            let index = MODIFIERS
+                .iter()
+                .position(|(modifier, _)| modifier.eq_ignore_ascii_case(part))
+                .ok_or_else(|| format!("Unknown modifier {} in hotkey {}", part, key))?;
+            modifiers[index] = true;
+        }
+        let name = match name.to_uppercase() {
+            name if name.starts_with("KEY_") || name.starts_with("BTN_") => name,
+            name if name == "-" => "KEY_MINUS".to_string(),
+            name => format!("KEY_{}", name),
+        };
+        Ok(Self {
+            key: name
+                .parse()
+                .map_err(|_| format!("Unknown hotkey {}", key))?,
+            modifiers,
+            command: command.to_string(),
+        })
+    }
+}
+
+/// Shared by the threads reading the devices
+#[derive(Clone)]
+struct Reader {
+    timer: Arc<Mutex<WlSplitTimer>>,
+    hotkeys: Arc<Vec<Hotkey>>,
+    /// Devices that are being read, by their path
+    opened: Arc<Mutex<HashSet<PathBuf>>>,
+}
+
+impl Reader {
+    /// Runs the hotkeys pressed on the device in a thread of its own, devices
+    /// without any of the keys are ignored
+    fn read(&self, path: PathBuf, mut device: Device) -> bool {
+        let supported = match device.supported_keys() {
+            Some(supported) => supported,
+            None => return false,
+        };
+        if !self
+            .hotkeys
+            .iter()
+            .any(|hotkey| supported.contains(hotkey.key))
+        {
+            return false;
+        }
+        self.opened.lock().unwrap().insert(path.clone());
+
+        let reader = self.clone();
+        thread::spawn(move || {
+            let mut pressed = HashSet::new();
+            // Fails once the device is unplugged
+            while let Ok(events) = device.fetch_events() {
+                for event in events {
+                    match event.destructure() {
+                        EventSummary::Key(_, key, 0) => {
+                            pressed.remove(&key);
+                        }
+                        // Repeated keys are ignored
+                        EventSummary::Key(_, key, 1) => {
+                            reader.press(key, &pressed);
+                            pressed.insert(key);
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            reader.opened.lock().unwrap().remove(&path);
+        });
+        true
+    }
+
+    fn press(&self, key: KeyCode, pressed: &HashSet<KeyCode>) {
+        let mut modifiers = [false; 4];
+        for (held, (_, keys)) in modifiers.iter_mut().zip(MODIFIERS.iter()) {
+            *held = keys.iter().any(|key| pressed.contains(key));
+        }
+        for hotkey in self.hotkeys.iter() {
+            if hotkey.key == key && hotkey.modifiers == modifiers {
+                if let Ok(request) = Request::parse(&hotkey.command) {
+                    protocol::execute(&self.timer, &request);
+                }
+            }
+        }
+    }
+}
+
+/// Runs socket commands for keys pressed anywhere, read from the keyboards in
+/// `/dev/input`, which needs the user to be in the `input` group. Keyboards
+/// connected later, including virtual ones created through uinput, are picked
+/// up as they appear. The keys still reach the focused window.
+pub fn spawn(
+    timer: Arc<Mutex<WlSplitTimer>>,
+    keys: &BTreeMap<String, String>,
+) -> Result<(), Box<dyn Error>> {
+    let hotkeys = keys
+        .iter()
+        .map(|(command, key)| Hotkey::parse(command, key))
+        .collect::<Result<_, _>>()?;
+    let reader = Reader {
+        timer,
+        hotkeys: Arc::new(hotkeys),
+        opened: Arc::default(),
+    };
+
+    // Watched first so that no device is missed in between
+    let mut inotify = Inotify::init()?;
+    inotify
+        .add_watch(INPUT_DIRECTORY, WatchMask::CREATE | WatchMask::ATTRIB)
+        .map_err(|e| format!("Unable to watch {}: {}", INPUT_DIRECTORY, e))?;
+    let mut found = false;
+    for (path, device) in evdev::enumerate() {
+        found |= reader.read(path, device);
+    }
+    if !found {
+        return Err(format!(
+            "No keyboard with the hotkeys can be read in {}, is the user in the input group?",
+            INPUT_DIRECTORY
+        )
+        .into());
+    }
+
+    thread::spawn(move || {
+        let mut buffer = [0; 4096];
+        loop {
+            let events = match inotify.read_events_blocking(&mut buffer) {
+                Ok(events) => events,
+                Err(e) => {
+                    eprintln!("Stopped watching for keyboards: {}", e);
+                    return;
+                }
+            };
+            for event in events {
+                let name = match event.name {
+                    Some(name) if name.to_string_lossy().starts_with("event") => name,
+                    _ => continue,
+                };
+                let path = Path::new(INPUT_DIRECTORY).join(name);
+                if reader.opened.lock().unwrap().contains(&path) {
+                    continue;
+                }
+                // udev only grants access after creating the device, which is
+                // seen as a change of its attributes
+                if let Ok(device) = Device::open(&path) {
+                    reader.read(path, device);
+                }
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use evdev::{uinput::VirtualDevice, AttributeSet, KeyEvent};
+    use livesplit_core::TimerPhase;
+
+    use super::*;
+    use crate::wl_split_timer::tests::{remove, timer};
+
+    fn parse(key: &str) -> Result<(KeyCode, [bool; 4]), String> {
+        Hotkey::parse("split", key).map(|hotkey| (hotkey.key, hotkey.modifiers))
+    }
+
+    #[test]
+    fn parses_modifiers() {
+        assert_eq!(parse("f1"), Ok((KeyCode::KEY_F1, [false; 4])));
+        assert_eq!(
+            parse("ctrl-r"),
+            Ok((KeyCode::KEY_R, [true, false, false, false]))
+        );
+        assert_eq!(
+            parse("Shift-Super-space"),
+            Ok((KeyCode::KEY_SPACE, [false, false, true, true]))
+        );
+    }
+
+    #[test]
+    fn parses_the_minus_key() {
+        assert_eq!(parse("-"), Ok((KeyCode::KEY_MINUS, [false; 4])));
+        assert_eq!(
+            parse("ctrl--"),
+            Ok((KeyCode::KEY_MINUS, [true, false, false, false]))
+        );
+        assert_eq!(
+            parse("ctrl-alt--"),
+            Ok((KeyCode::KEY_MINUS, [true, true, false, false]))
+        );
+    }
+
+    #[test]
+    fn parses_evdev_names() {
+        assert_eq!(
+            parse("KEY_PLAYPAUSE"),
+            Ok((KeyCode::KEY_PLAYPAUSE, [false; 4]))
+        );
+        assert_eq!(
+            parse("alt-btn_0"),
+            Ok((KeyCode::BTN_0, [false, true, false, false]))
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        assert_eq!(parse("ctrl-nokey"), Err("Unknown hotkey ctrl-nokey".into()));
+        assert_eq!(parse("ctrl-"), Err("Unknown hotkey ctrl-".into()));
+        assert_eq!(
+            parse("hyper-r"),
+            Err("Unknown modifier hyper in hotkey hyper-r".into())
+        );
+    }
+
+    /// Needs write access to `/dev/uinput` and read access to `/dev/input`,
+    /// run with `cargo test --features hotkeys -- --ignored`
+    #[test]
+    #[ignore]
+    fn runs_commands_of_pressed_keys() {
+        let mut keys = AttributeSet::<KeyCode>::new();
+        keys.insert(KeyCode::KEY_LEFTCTRL);
+        keys.insert(KeyCode::KEY_R);
+        let mut keyboard = VirtualDevice::builder()
+            .expect("Unable to open /dev/uinput")
+            .name("wlsplit hotkeys test")
+            .with_keys(&keys)
+            .unwrap()
+            .build()
+            .unwrap();
+        keyboard.enumerate_dev_nodes_blocking().unwrap().next();
+
+        let timer = Arc::new(Mutex::new(timer("hotkeys")));
+        let hotkeys = BTreeMap::from([("start".to_string(), "ctrl-r".to_string())]);
+        spawn(Arc::clone(&timer), &hotkeys).unwrap();
+        // The reading threads have to be waiting for events
+        thread::sleep(Duration::from_millis(200));
+
+        for (key, value) in [
+            (KeyCode::KEY_LEFTCTRL, 1),
+            (KeyCode::KEY_R, 1),
+            (KeyCode::KEY_R, 0),
+            (KeyCode::KEY_LEFTCTRL, 0),
+        ] {
+            keyboard.emit(&[*KeyEvent::new(key, value)]).unwrap();
+        }
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while timer.lock().unwrap().timer().current_phase() == TimerPhase::NotRunning
+            && Instant::now() < deadline
+        {
+            thread::sleep(Duration::from_millis(10));
+        }
+        let phase = timer.lock().unwrap().timer().current_phase();
+        remove(&timer.lock().unwrap());
+        assert_eq!(phase, TimerPhase::Running);
+    }
+}
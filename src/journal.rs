@@ -0,0 +1,477 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, stdin, stdout, BufRead, BufReader, IsTerminal, Write},
+    str::FromStr,
+};
+
+use chrono::{DateTime, Utc};
+use livesplit_core::{AtomicDateTime, Run, Time, TimeSpan, TimingMethod};
+use serde::{Deserialize, Serialize};
+
+use crate::{event::Event, time_format::TimeFormat};
+
+/// What to do with an attempt that was still running when wlsplit last exited
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Recovery {
+    Ask,
+    Resume,
+    Commit,
+    Discard,
+}
+
+impl FromStr for Recovery {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ask" => Ok(Recovery::Ask),
+            "resume" | "r" => Ok(Recovery::Resume),
+            "commit" | "c" => Ok(Recovery::Commit),
+            "discard" | "d" => Ok(Recovery::Discard),
+            _ => Err(format!("Unknown recovery mode: {}", s)),
+        }
+    }
+}
+
+impl Recovery {
+    /// Prompts for a decision if stdin is a terminal, otherwise the attempt is
+    /// committed so it is never lost without asking.
+    pub fn resolve(self, interrupted: &Interrupted) -> Self {
+        if self != Recovery::Ask {
+            return self;
+        }
+        if !stdin().is_terminal() {
+            return Recovery::Commit;
+        }
+        loop {
+            print!(
+                "Found an interrupted attempt at {} ({} splits done). [r]esume, [c]ommit to history or [d]iscard? ",
+                interrupted
+                    .time
+                    .real_time
+                    .map(|time| TimeFormat::for_file().format_time_span(time))
+                    .unwrap_or_default(),
+                interrupted.splits.len()
+            );
+            stdout().flush().ok();
+            let mut answer = String::new();
+            if stdin().read_line(&mut answer).unwrap_or(0) == 0 {
+                return Recovery::Commit;
+            }
+            match answer.trim().parse() {
+                Ok(Recovery::Ask) | Err(_) => continue,
+                Ok(recovery) => return recovery,
+            }
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Entry {
+    timestamp: String,
+    real_time: Option<String>,
+    game_time: Option<String>,
+    event: Event,
+}
+
+/// Append-only log of the current attempt, written next to the splits file so
+/// an attempt survives a crash of wlsplit or the compositor.
+pub struct Journal {
+    path: String,
+    file: Option<File>,
+}
+
+impl Journal {
+    pub fn new(file: &str) -> Self {
+        Self {
+            path: format!("{}.journal", file),
+            file: None,
+        }
+    }
+
+    pub fn record(&mut self, event: &Event, time: Time) -> io::Result<()> {
+        let file = match &mut self.file {
+            Some(file) => file,
+            None => self.file.insert(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&self.path)?,
+            ),
+        };
+        let entry = Entry {
+            timestamp: Utc::now().to_rfc3339(),
+            real_time: time.real_time.map(format_time),
+            game_time: time.game_time.map(format_time),
+            event: event.clone(),
+        };
+        writeln!(file, "{}", serde_json::to_string(&entry)?)?;
+        file.sync_data()
+    }
+
+    pub fn clear(&mut self) {
+        self.file = None;
+        fs::remove_file(&self.path).ok();
+    }
+
+    /// Replays the journal, returns the attempt that was running when it ended
+    pub fn read(&self) -> Option<Interrupted> {
+        let file = File::open(&self.path).ok()?;
+        let mut interrupted: Option<Interrupted> = None;
+        let mut undone: Vec<Time> = Vec::new();
+
+        // A crash can leave a partially written last line behind, which is skipped
+        for entry in BufReader::new(file)
+            .lines()
+            .map_while(Result::ok)
+            .filter_map(|line| serde_json::from_str::<Entry>(&line).ok())
+        {
+            let timestamp = match DateTime::parse_from_rfc3339(&entry.timestamp) {
+                Ok(timestamp) => timestamp.with_timezone(&Utc),
+                Err(_) => continue,
+            };
+            let time = Time::new()
+                .with_real_time(entry.real_time.and_then(|time| time.parse().ok()))
+                .with_game_time(entry.game_time.and_then(|time| time.parse().ok()));
+            if let Event::Started = entry.event {
+                undone.clear();
+                interrupted = Some(Interrupted {
+                    started: timestamp,
+                    splits: Vec::new(),
+                    paused: false,
+                    game_time_paused: false,
+                    time,
+                    timestamp,
+                });
+                continue;
+            }
+            let attempt = match &mut interrupted {
+                Some(attempt) => attempt,
+                None => continue,
+            };
+            match entry.event {
+                Event::Split { index, .. } => {
+                    undone.clear();
+                    attempt.splits.truncate(index);
+                    attempt.splits.push(time);
+                }
+                Event::Skipped { index, .. } => {
+                    undone.clear();
+                    attempt.splits.truncate(index);
+                    attempt.splits.push(Time::default());
+                }
+                Event::Undo { index, .. } => {
+                    while attempt.splits.len() > index {
+                        undone.extend(attempt.splits.pop());
+                    }
+                }
+                Event::Redo => attempt.splits.extend(undone.pop()),
+                Event::Paused => attempt.paused = true,
+                Event::Resumed => attempt.paused = false,
+                Event::GameTimePaused => attempt.game_time_paused = true,
+                Event::GameTimeResumed => attempt.game_time_paused = false,
+                Event::Reset => {
+                    interrupted = None;
+                    continue;
+                }
+                _ => {}
+            }
+            attempt.time = time;
+            attempt.timestamp = timestamp;
+        }
+        interrupted
+    }
+}
+
+/// An attempt restored from the journal
+#[derive(Debug, Clone)]
+pub struct Interrupted {
+    pub started: DateTime<Utc>,
+    /// Split times of the finished segments, empty for skipped ones
+    pub splits: Vec<Time>,
+    pub paused: bool,
+    pub game_time_paused: bool,
+    /// Timer value at the last journal entry, including game time that was
+    /// set
+    pub time: Time,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl Interrupted {
+    pub fn is_finished(&self, run: &Run) -> bool {
+        self.splits.len() >= run.len()
+    }
+
+    /// Timer value the attempt would be at now, a running timer kept running
+    /// while wlsplit was gone. Paused game time stays where it was.
+    pub fn elapsed(&self) -> Time {
+        if self.paused {
+            return self.time;
+        }
+        let since = TimeSpan::from(Utc::now() - self.timestamp);
+        let game_time = if self.game_time_paused {
+            self.time.game_time
+        } else {
+            self.time.game_time.map(|time| time + since)
+        };
+        Time::new()
+            .with_real_time(self.time.real_time.map(|time| time + since))
+            .with_game_time(game_time)
+    }
+
+    /// The timer starts at the run's offset, so that is taken out of the real
+    /// time before comparing it with the wall time
+    fn pause_time(&self, offset: TimeSpan) -> Option<TimeSpan> {
+        let wall_time = TimeSpan::from(self.timestamp - self.started);
+        let pause_time = wall_time - (self.time.real_time? - offset);
+        // Entries are timestamped just after reading the timer, which adds a
+        // few milliseconds of noise
+        Some(pause_time).filter(|time| *time > TimeSpan::from_milliseconds(10.0))
+    }
+
+    /// Stores the attempt in the run the same way livesplit-core does when a
+    /// timer is reset with updated splits.
+    pub fn commit(&self, run: &mut Run, method: TimingMethod) {
+        for (segment, time) in run.segments_mut().iter_mut().zip(&self.splits) {
+            segment.set_split_time(*time);
+        }
+        let time = if self.is_finished(run) {
+            self.time
+        } else {
+            Time::default()
+        };
+        run.add_attempt(
+            time,
+            Some(AtomicDateTime::new(self.started, false)),
+            Some(AtomicDateTime::new(self.timestamp, false)),
+            self.pause_time(run.offset()),
+        );
+        update_best_segments(run);
+        update_pb_splits(run, method);
+        run.update_segment_history(self.splits.len());
+        clear_splits(run);
+    }
+}
+
+pub fn clear_splits(run: &mut Run) {
+    for segment in run.segments_mut() {
+        segment.clear_split_time();
+    }
+    run.fix_splits();
+    run.regenerate_comparisons();
+}
+
+fn update_best_segments(run: &mut Run) {
+    let mut previous = Time::zero();
+    for segment in run.segments_mut() {
+        let mut best_segment_time = segment.best_segment_time();
+        for method in TimingMethod::all().iter().copied() {
+            if let Some(split_time) = segment.split_time()[method] {
+                let segment_time = previous[method].map(|previous| split_time - previous);
+                previous[method] = Some(split_time);
+                if best_segment_time[method]
+                    .is_none_or(|best| segment_time.is_some_and(|time| time < best))
+                {
+                    best_segment_time[method] = segment_time;
+                }
+            }
+        }
+        segment.set_best_segment_time(best_segment_time);
+    }
+}
+
+fn update_pb_splits(run: &mut Run, method: TimingMethod) {
+    let last = run.segments().last().unwrap();
+    let split_time = last.split_time()[method];
+    let pb_split_time = last.personal_best_split_time()[method];
+    if split_time.is_some_and(|time| pb_split_time.is_none_or(|pb| time < pb)) {
+        run.import_pb_into_segment_history();
+        run.fix_splits();
+        for segment in run.segments_mut() {
+            let split_time = segment.split_time();
+            segment.set_personal_best_split_time(split_time);
+        }
+        run.clear_run_id();
+    }
+}
+
+fn format_time(time: TimeSpan) -> String {
+    TimeFormat::for_file().format_time_span(time)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use chrono::Duration;
+    use livesplit_core::Segment;
+
+    use super::*;
+
+    fn journal(name: &str) -> Journal {
+        let file = env::temp_dir().join(format!("wlsplit-{}-{}.json", name, process::id()));
+        let mut journal = Journal::new(&file.to_string_lossy());
+        journal.clear();
+        journal
+    }
+
+    fn time(seconds: f64) -> Time {
+        Time::new().with_real_time(Some(TimeSpan::from_seconds(seconds)))
+    }
+
+    fn split(index: usize) -> Event {
+        Event::Split {
+            index,
+            segment: String::new(),
+            time: None,
+            delta: None,
+        }
+    }
+
+    fn undo(index: usize) -> Event {
+        Event::Undo {
+            index,
+            segment: String::new(),
+        }
+    }
+
+    #[test]
+    fn replays_the_running_attempt() {
+        let mut journal = journal("journal-replay");
+        journal.record(&Event::Started, time(0.0)).unwrap();
+        journal.record(&split(0), time(1.0)).unwrap();
+        journal.record(&split(1), time(2.0)).unwrap();
+        journal.record(&undo(1), time(2.5)).unwrap();
+        journal.record(&Event::Redo, time(3.0)).unwrap();
+        let skipped = Event::Skipped {
+            index: 2,
+            segment: String::new(),
+        };
+        journal.record(&skipped, time(4.0)).unwrap();
+        journal.record(&Event::Paused, time(5.0)).unwrap();
+        // A line cut off by a crash
+        write!(journal.file.as_mut().unwrap(), "{{\"timestamp\":").unwrap();
+
+        let interrupted = journal.read().unwrap();
+        assert_eq!(interrupted.splits, [time(1.0), time(2.0), Time::default()]);
+        assert!(interrupted.paused);
+        assert_eq!(interrupted.time, time(5.0));
+        assert_eq!(interrupted.elapsed(), time(5.0));
+        journal.clear();
+    }
+
+    #[test]
+    fn forgets_reset_attempts() {
+        let mut journal = journal("journal-reset");
+        assert!(journal.read().is_none());
+        journal.record(&Event::Started, time(0.0)).unwrap();
+        journal.record(&split(0), time(1.0)).unwrap();
+        journal.record(&Event::Reset, time(1.5)).unwrap();
+        assert!(journal.read().is_none());
+
+        journal.record(&Event::Started, time(0.0)).unwrap();
+        assert!(journal.read().unwrap().splits.is_empty());
+        journal.clear();
+    }
+
+    fn run() -> Run {
+        let mut run = Run::new();
+        for name in ["a", "b", "c"] {
+            run.push_segment(Segment::new(name));
+        }
+        run
+    }
+
+    fn attempt(splits: Vec<Time>, time: Time) -> Interrupted {
+        let timestamp = Utc::now();
+        Interrupted {
+            started: timestamp - Duration::seconds(3),
+            splits,
+            paused: false,
+            game_time_paused: false,
+            time,
+            timestamp,
+        }
+    }
+
+    #[test]
+    fn commits_finished_attempts_as_personal_best() {
+        let mut run = run();
+        let attempt = attempt(vec![time(1.0), time(2.5), time(3.0)], time(3.0));
+        attempt.commit(&mut run, TimingMethod::RealTime);
+
+        assert_eq!(run.attempt_history().len(), 1);
+        assert_eq!(run.attempt_history()[0].time(), time(3.0));
+        let best: Vec<_> = run
+            .segments()
+            .iter()
+            .map(|segment| segment.best_segment_time())
+            .collect();
+        assert_eq!(best, [time(1.0), time(1.5), time(0.5)]);
+        let pb: Vec<_> = run
+            .segments()
+            .iter()
+            .map(|segment| segment.personal_best_split_time())
+            .collect();
+        assert_eq!(pb, [time(1.0), time(2.5), time(3.0)]);
+        assert!(run
+            .segments()
+            .iter()
+            .all(|segment| segment.split_time() == Time::default()));
+    }
+
+    #[test]
+    fn commits_unfinished_attempts_without_a_time() {
+        let mut run = run();
+        let attempt = attempt(vec![time(1.0)], time(1.2));
+        assert!(!attempt.is_finished(&run));
+        attempt.commit(&mut run, TimingMethod::RealTime);
+
+        assert_eq!(run.attempt_history().len(), 1);
+        assert_eq!(run.attempt_history()[0].time(), Time::default());
+        assert_eq!(run.segment(0).best_segment_time(), time(1.0));
+        assert_eq!(run.segment(0).personal_best_split_time(), Time::default());
+    }
+
+    #[test]
+    fn keeps_paused_game_time() {
+        let mut journal = journal("journal-game-time");
+        let time = |real, game| {
+            Time::new()
+                .with_real_time(Some(TimeSpan::from_seconds(real)))
+                .with_game_time(Some(TimeSpan::from_seconds(game)))
+        };
+        journal.record(&Event::Started, time(0.0, 0.0)).unwrap();
+        journal.record(&Event::GameTimeSet, time(1.0, 5.0)).unwrap();
+        journal
+            .record(&Event::GameTimePaused, time(2.0, 6.0))
+            .unwrap();
+
+        let mut interrupted = journal.read().unwrap();
+        assert!(interrupted.game_time_paused);
+        assert_eq!(interrupted.time, time(2.0, 6.0));
+        interrupted.timestamp -= Duration::seconds(2);
+        let elapsed = interrupted.elapsed();
+        assert!(elapsed.real_time.unwrap() >= TimeSpan::from_seconds(4.0));
+        assert_eq!(elapsed.game_time, Some(TimeSpan::from_seconds(6.0)));
+
+        journal
+            .record(&Event::GameTimeResumed, time(3.0, 6.0))
+            .unwrap();
+        assert!(!journal.read().unwrap().game_time_paused);
+        journal.clear();
+    }
+
+    #[test]
+    fn leaves_the_offset_out_of_the_pause_time() {
+        let mut run = run();
+        run.set_offset(TimeSpan::from_seconds(-2.0));
+        // Started 3 seconds ago at -2, so nothing was paused
+        attempt(vec![time(1.0)], time(1.0)).commit(&mut run, TimingMethod::RealTime);
+        assert_eq!(run.attempt_history()[0].pause_time(), None);
+
+        attempt(vec![time(0.5)], time(0.5)).commit(&mut run, TimingMethod::RealTime);
+        let pause_time = run.attempt_history()[1].pause_time().unwrap();
+        assert!((pause_time.total_seconds() - 0.5).abs() < 0.01);
+    }
+}
@@ -0,0 +1,823 @@
+use std::{error::Error, fs::File, io::BufReader, ops::RangeInclusive};
+
+use livesplit_core::{
+    comparison,
+    component::text::Text,
+    layout::{
+        parser as lsl_parser, Component as LivesplitComponent, ComponentState, GeneralSettings,
+        Layout as LivesplitLayout,
+    },
+    settings::{Color as LivesplitColor, Gradient, SemanticColor},
+    Segment, TimeSpan, TimerPhase, TimingMethod,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{config::Config, time_format::TimeFormat, wl_split_timer::WlSplitTimer};
+
+/// A building block of the layout, displays render the lines these produce
+/// from top to bottom in the order given in the config
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type")]
+pub enum Component {
+    /// Game, category and comparison with the attempt count
+    Title,
+    /// One line per segment
+    Splits,
+    /// The running time, followed by the other timing method if
+    /// `show_both_timing_methods` is set
+    Timer,
+    /// Time spent in the current segment
+    SegmentTimer,
+    /// Time gained or lost in the last segment compared to the comparison
+    PreviousSegment,
+    SumOfBest,
+    BestPossibleTime,
+    /// How much faster the current segment can be done, based on the best segment
+    PossibleTimeSave,
+    Attempts,
+    Text {
+        #[serde(default)]
+        left: String,
+        #[serde(default)]
+        right: String,
+    },
+}
+
+impl Component {
+    pub fn default_layout() -> Vec<Component> {
+        vec![
+            Component::Title,
+            Component::Splits,
+            Component::Timer,
+            Component::PreviousSegment,
+            Component::SumOfBest,
+            Component::BestPossibleTime,
+        ]
+    }
+}
+
+/// The built-in components only use the first four, the others come from
+/// the semantic colors of LiveSplit layouts
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum Color {
+    Normal,
+    /// Ahead and gaining time
+    Gain,
+    /// Behind and losing time
+    Loss,
+    Gold,
+    AheadLosing,
+    BehindGaining,
+    PersonalBest,
+    NotRunning,
+    Paused,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(tag = "type")]
+pub enum Kind {
+    Title,
+    Split {
+        current: bool,
+    },
+    Timer,
+    Info,
+    /// A horizontal rule, the line has no text
+    Separator,
+}
+
+/// Colors lines are drawn with, in `[a, r, g, b]` order like in the config
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Palette {
+    pub background: [u8; 4],
+    pub text: [u8; 4],
+    pub separator: [u8; 4],
+    pub gain: [u8; 4],
+    pub loss: [u8; 4],
+    pub gold: [u8; 4],
+    pub ahead_losing: [u8; 4],
+    pub behind_gaining: [u8; 4],
+    pub personal_best: [u8; 4],
+    pub not_running: [u8; 4],
+    pub paused: [u8; 4],
+}
+
+impl Palette {
+    /// The colors of the config, the semantic colors it has no setting for
+    /// share the closest one
+    pub fn from_config(config: &Config) -> Self {
+        let [alpha, r, g, b] = config.font_color;
+        Self {
+            background: [
+                config.background_opacity,
+                config.background_color[0],
+                config.background_color[1],
+                config.background_color[2],
+            ],
+            text: config.font_color,
+            separator: [alpha / 3, r, g, b],
+            gain: config.font_color_gain,
+            loss: config.font_color_loss,
+            gold: config.font_color_gold,
+            ahead_losing: config.font_color_gain,
+            behind_gaining: config.font_color_loss,
+            personal_best: config.font_color_gold,
+            not_running: config.font_color,
+            paused: config.font_color,
+        }
+    }
+
+    /// The colors of a LiveSplit layout. A gradient background is drawn in
+    /// the average of its colors.
+    fn from_livesplit(settings: &GeneralSettings) -> Self {
+        let semantic = |color: SemanticColor| argb(color.visualize(settings));
+        Self {
+            background: match settings.background {
+                Gradient::Transparent => [0; 4],
+                Gradient::Plain(color) => argb(color),
+                Gradient::Vertical(first, second) | Gradient::Horizontal(first, second) => {
+                    let [first, second] = [argb(first), argb(second)];
+                    [0, 1, 2, 3].map(|i| ((first[i] as u16 + second[i] as u16) / 2) as u8)
+                }
+            },
+            text: argb(settings.text_color),
+            separator: argb(settings.separators_color),
+            gain: semantic(SemanticColor::AheadGainingTime),
+            loss: semantic(SemanticColor::BehindLosingTime),
+            gold: semantic(SemanticColor::BestSegment),
+            ahead_losing: semantic(SemanticColor::AheadLosingTime),
+            behind_gaining: semantic(SemanticColor::BehindGainingTime),
+            personal_best: semantic(SemanticColor::PersonalBest),
+            not_running: semantic(SemanticColor::NotRunning),
+            paused: semantic(SemanticColor::Paused),
+        }
+    }
+
+    pub fn color(&self, color: Color) -> [u8; 4] {
+        match color {
+            Color::Normal => self.text,
+            Color::Gain => self.gain,
+            Color::Loss => self.loss,
+            Color::Gold => self.gold,
+            Color::AheadLosing => self.ahead_losing,
+            Color::BehindGaining => self.behind_gaining,
+            Color::PersonalBest => self.personal_best,
+            Color::NotRunning => self.not_running,
+            Color::Paused => self.paused,
+        }
+    }
+}
+
+/// livesplit-core keeps the 8 bit channels of layout files as they are, only
+/// scaled to floats
+fn argb(color: LivesplitColor) -> [u8; 4] {
+    let (r, g, b, a) = color.rgba.into_components();
+    [a, r, g, b].map(|channel| (channel.clamp(0.0, 1.0) * 255.0).round() as u8)
+}
+
+/// A single row of the layout: a label on the left, a value on the right and
+/// optionally a delta in between
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Line {
+    pub kind: Kind,
+    pub text: String,
+    pub delta: Option<(String, Color)>,
+    pub value: String,
+    pub color: Color,
+}
+
+impl Line {
+    fn info(text: &str, value: String) -> Self {
+        Self {
+            kind: Kind::Info,
+            text: text.to_string(),
+            delta: None,
+            value,
+            color: Color::Normal,
+        }
+    }
+}
+
+pub struct Layout {
+    components: Vec<Component>,
+    show_both_timing_methods: bool,
+    visible_splits: usize,
+    upcoming_splits: usize,
+    /// LiveSplit layout used instead of the components if set
+    livesplit: Option<LivesplitLayout>,
+    palette: Palette,
+}
+
+impl Layout {
+    pub fn new(config: &Config) -> Result<Self, Box<dyn Error>> {
+        let livesplit = match &config.layout_file {
+            Some(path) => {
+                let file = File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+                let mut layout = lsl_parser::parse(BufReader::new(file))
+                    .map_err(|e| format!("{}: {}", path, e))?;
+                let len = layout.components.len();
+                layout
+                    .components
+                    .retain(|component| !matches!(component, LivesplitComponent::Graph(_)));
+                if layout.components.len() < len {
+                    eprintln!("{}: graphs can't be displayed and are left out", path);
+                }
+                Some(layout)
+            }
+            None => None,
+        };
+        let palette = match &livesplit {
+            Some(layout) => Palette::from_livesplit(layout.general_settings()),
+            None => Palette::from_config(config),
+        };
+        Ok(Self {
+            components: config.layout.clone(),
+            show_both_timing_methods: config.show_both_timing_methods,
+            visible_splits: config.visible_splits,
+            upcoming_splits: config.upcoming_splits,
+            livesplit,
+            palette,
+        })
+    }
+
+    /// The colors of the LiveSplit layout if there is one, otherwise the ones
+    /// of the config
+    pub fn palette(&self) -> Palette {
+        self.palette
+    }
+
+    pub fn lines(&mut self, timer: &WlSplitTimer) -> Vec<Line> {
+        if let Some(layout) = &mut self.livesplit {
+            return layout
+                .state(timer.timer())
+                .components
+                .into_iter()
+                .flat_map(livesplit_lines)
+                .collect();
+        }
+        let mut lines = Vec::new();
+        for component in &self.components {
+            match component {
+                Component::Title => lines.push(Line {
+                    kind: Kind::Title,
+                    text: format!(
+                        "{} ({}) - {}",
+                        timer.game_name(),
+                        timer.category_name(),
+                        comparison::shorten(timer.comparison())
+                    ),
+                    delta: None,
+                    value: timer.run().attempt_count().to_string(),
+                    color: Color::Normal,
+                }),
+                Component::Splits => lines.extend(self.split_lines(timer)),
+                Component::Timer => {
+                    lines.push(Line {
+                        kind: Kind::Timer,
+                        text: String::new(),
+                        delta: None,
+                        value: format_time(timer.time()),
+                        color: Color::Normal,
+                    });
+                    if self.show_both_timing_methods {
+                        let method = timer.secondary_timing_method();
+                        lines.push(Line::info(
+                            match method {
+                                TimingMethod::RealTime => "Real time",
+                                TimingMethod::GameTime => "Game time",
+                            },
+                            format_time(timer.time_with_method(method)),
+                        ));
+                    }
+                }
+                Component::SegmentTimer => {
+                    lines.push(Line::info("Segment time", format_time(segment_time(timer))))
+                }
+                Component::PreviousSegment => {
+                    let mut line = Line::info("Previous segment", "-".to_string());
+                    if let Some(index) = timer.current_segment_index().filter(|index| *index > 0) {
+                        let segment_time = timer
+                            .get_segment_time(index - 1)
+                            .map(|msecs| TimeSpan::from_milliseconds(msecs as f64));
+                        if let Some((diff, color)) = diff_time(
+                            segment_time,
+                            timer.comparison_segment_time(index - 1),
+                            is_gold(timer, index - 1..=index - 1),
+                        ) {
+                            line.value = diff;
+                            line.color = color;
+                        }
+                    }
+                    lines.push(line);
+                }
+                Component::SumOfBest => lines.push(Line::info(
+                    "Sum of best segments",
+                    TimeFormat::default().format_time(timer.sum_of_best_segments() as u128, false),
+                )),
+                Component::BestPossibleTime => lines.push(Line::info(
+                    "Best possible time",
+                    TimeFormat::default().format_time(timer.best_possible_time() as u128, false),
+                )),
+                Component::PossibleTimeSave => {
+                    let method = timer.timing_method();
+                    let save = timer.current_segment_index().and_then(|index| {
+                        let best = timer.segment_best_time(index)[method]?;
+                        Some(timer.comparison_segment_time(index)? - best)
+                    });
+                    lines.push(Line::info("Possible time save", format_time(save)));
+                }
+                Component::Attempts => lines.push(Line::info(
+                    "Attempts",
+                    timer.run().attempt_count().to_string(),
+                )),
+                Component::Text { left, right } => lines.push(Line::info(left, right.clone())),
+            }
+        }
+        lines
+    }
+
+    /// Indices of the splits in view, scrolled so the current split and the
+    /// upcoming ones are visible. The last split stays pinned at the bottom.
+    fn visible_split_indices(&self, len: usize, current: usize) -> Vec<usize> {
+        if self.visible_splits == 0 || self.visible_splits >= len {
+            return (0..len).collect();
+        }
+        let pinned = if self.visible_splits > 1 { 1 } else { 0 };
+        let window = self.visible_splits - pinned;
+        let upcoming = self.upcoming_splits.min(window - 1);
+        let start = (current + upcoming + 1)
+            .saturating_sub(window)
+            .min(len - pinned - window);
+        let mut indices: Vec<usize> = (start..start + window).collect();
+        if pinned == 1 {
+            indices.push(len - 1);
+        }
+        indices
+    }
+
+    fn split_lines(&self, timer: &WlSplitTimer) -> Vec<Line> {
+        let method = timer.timing_method();
+        let index = timer.current_segment_index().unwrap_or(0);
+        let rows = split_rows(timer.segments(), index);
+        let current = rows
+            .iter()
+            .position(|row| !row.header && row.index == index)
+            .unwrap_or(rows.len());
+        self.visible_split_indices(rows.len(), current)
+            .into_iter()
+            .map(|row| {
+                let row = &rows[row];
+                if row.header {
+                    return Line {
+                        kind: Kind::Split { current: false },
+                        text: row.name.clone(),
+                        delta: None,
+                        value: String::new(),
+                        color: Color::Normal,
+                    };
+                }
+                let i = row.index;
+                let segment = &timer.segments()[i];
+                let time = if let Some(time) = timer.comparison_split_time(i) {
+                    Some(time)
+                } else if segment.segment_history().iter().len() == 0 {
+                    segment.split_time()[method]
+                } else {
+                    None
+                };
+                let delta = match i.cmp(&index) {
+                    std::cmp::Ordering::Equal => {
+                        diff_time(timer.time(), timer.comparison_split_time(i), false)
+                    }
+                    std::cmp::Ordering::Less => diff_time(
+                        segment.split_time()[method],
+                        timer.comparison_split_time(i),
+                        is_gold(timer, row.first..=i),
+                    ),
+                    std::cmp::Ordering::Greater => None,
+                };
+                Line {
+                    kind: Kind::Split {
+                        current: i == index,
+                    },
+                    text: row.name.clone(),
+                    delta,
+                    value: format_time(time),
+                    color: Color::Normal,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Turns a LiveSplit component into lines, their semantic colors are drawn in
+/// the colors of the layout's palette
+fn livesplit_lines(component: ComponentState) -> Vec<Line> {
+    let (text, value, color) = match component {
+        ComponentState::Title(title) => {
+            let text = match title.line2 {
+                Some(line2) => format!("{} - {}", title.line1, line2),
+                None => title.line1,
+            };
+            let value = match (title.finished_runs, title.attempts) {
+                (Some(finished), Some(attempts)) => format!("{}/{}", finished, attempts),
+                (None, Some(attempts)) => attempts.to_string(),
+                _ => String::new(),
+            };
+            return vec![Line {
+                kind: Kind::Title,
+                text,
+                delta: None,
+                value,
+                color: Color::Normal,
+            }];
+        }
+        ComponentState::Splits(splits) => {
+            // The first column is the rightmost one
+            return splits
+                .splits
+                .into_iter()
+                .map(|split| {
+                    let mut columns = split.columns.into_iter();
+                    let value = columns.next();
+                    let delta = columns
+                        .next()
+                        .map(|delta| (delta.value, semantic_color(delta.semantic_color)));
+                    Line {
+                        kind: Kind::Split {
+                            current: split.is_current_split,
+                        },
+                        text: split.name,
+                        delta: delta.filter(|(delta, _)| !delta.is_empty()),
+                        color: value
+                            .as_ref()
+                            .map_or(Color::Normal, |value| semantic_color(value.semantic_color)),
+                        value: value.map(|value| value.value).unwrap_or_default(),
+                    }
+                })
+                .collect();
+        }
+        ComponentState::Timer(timer) => {
+            return vec![Line {
+                kind: Kind::Timer,
+                text: String::new(),
+                delta: None,
+                value: timer.time + &timer.fraction,
+                color: semantic_color(timer.semantic_color),
+            }]
+        }
+        ComponentState::DetailedTimer(detailed) => {
+            let mut lines = vec![Line {
+                kind: Kind::Timer,
+                text: detailed.segment_name.unwrap_or_default(),
+                delta: None,
+                value: detailed.timer.time + &detailed.timer.fraction,
+                color: semantic_color(detailed.timer.semantic_color),
+            }];
+            lines.push(Line::info(
+                "Segment time",
+                detailed.segment_timer.time + &detailed.segment_timer.fraction,
+            ));
+            for comparison in vec![detailed.comparison1, detailed.comparison2]
+                .into_iter()
+                .flatten()
+            {
+                lines.push(Line::info(&comparison.name, comparison.time));
+            }
+            return lines;
+        }
+        ComponentState::Text(text) => match text.text {
+            Text::Center(text) => (text, String::new(), Color::Normal),
+            Text::Split(left, right) => (left, right, Color::Normal),
+        },
+        ComponentState::PreviousSegment(state) => {
+            (state.text, state.time, semantic_color(state.semantic_color))
+        }
+        ComponentState::Delta(state) => {
+            (state.text, state.time, semantic_color(state.semantic_color))
+        }
+        ComponentState::SumOfBest(state) => (state.text, state.time, Color::Normal),
+        ComponentState::PossibleTimeSave(state) => (state.text, state.time, Color::Normal),
+        ComponentState::CurrentPace(state) => (state.text, state.time, Color::Normal),
+        ComponentState::TotalPlaytime(state) => (state.text, state.time, Color::Normal),
+        ComponentState::CurrentComparison(state) => (state.text, state.comparison, Color::Normal),
+        ComponentState::BlankSpace(_) => (String::new(), String::new(), Color::Normal),
+        ComponentState::Separator(_) => {
+            return vec![Line {
+                kind: Kind::Separator,
+                ..Line::info("", String::new())
+            }]
+        }
+        // Graphs are left out when the layout is read
+        ComponentState::Graph(_) => return Vec::new(),
+    };
+    vec![Line {
+        color,
+        ..Line::info(&text, value)
+    }]
+}
+
+fn semantic_color(color: SemanticColor) -> Color {
+    match color {
+        SemanticColor::Default => Color::Normal,
+        SemanticColor::AheadGainingTime => Color::Gain,
+        SemanticColor::AheadLosingTime => Color::AheadLosing,
+        SemanticColor::BehindGainingTime => Color::BehindGaining,
+        SemanticColor::BehindLosingTime => Color::Loss,
+        SemanticColor::BestSegment => Color::Gold,
+        SemanticColor::PersonalBest => Color::PersonalBest,
+        SemanticColor::NotRunning => Color::NotRunning,
+        SemanticColor::Paused => Color::Paused,
+    }
+}
+
+/// A row of the splits list, either a single segment or a group of subsplits
+/// displayed with the times of the segment ending it
+struct SplitRow {
+    index: usize,
+    first: usize,
+    name: String,
+    /// Title row above the subsplits of the expanded group
+    header: bool,
+}
+
+/// Groups segments by LiveSplit's naming convention: subsplits start with `-`
+/// and the segment ending a group may be named `{Group} Segment`. The group
+/// containing the current split is expanded, all others are collapsed.
+fn split_rows(segments: &[Segment], current: usize) -> Vec<SplitRow> {
+    let mut rows = Vec::new();
+    let mut first = 0;
+    for (index, segment) in segments.iter().enumerate() {
+        if segment.name().starts_with('-') && index + 1 < segments.len() {
+            continue;
+        }
+        let (group, name) = group_name(segment.name());
+        if first == index && group.is_none() {
+            rows.push(SplitRow {
+                index,
+                first,
+                name: name.to_string(),
+                header: false,
+            });
+        } else if (first..=index).contains(&current) {
+            rows.push(SplitRow {
+                index,
+                first,
+                name: group.unwrap_or(name).to_string(),
+                header: true,
+            });
+            rows.extend((first..=index).map(|index| SplitRow {
+                index,
+                first: index,
+                name: format!("  {}", group_name(segments[index].name()).1),
+                header: false,
+            }));
+        } else {
+            rows.push(SplitRow {
+                index,
+                first,
+                name: group.unwrap_or(name).to_string(),
+                header: false,
+            });
+        }
+        first = index + 1;
+    }
+    rows
+}
+
+/// Splits a segment name into its group and the name without the subsplit
+/// markers
+fn group_name(name: &str) -> (Option<&str>, &str) {
+    let name = name.strip_prefix('-').unwrap_or(name);
+    match name.strip_prefix('{').and_then(|name| name.split_once('}')) {
+        Some((group, name)) => (Some(group), name.trim_start()),
+        None => (None, name),
+    }
+}
+
+fn segment_time(timer: &WlSplitTimer) -> Option<TimeSpan> {
+    if timer.timer().current_phase() == TimerPhase::NotRunning {
+        return None;
+    }
+    let index = timer.current_segment_index()?;
+    let start = match index {
+        0 => Some(TimeSpan::zero()),
+        _ => timer.segment_split_time(index - 1)[timer.timing_method()],
+    };
+    Some(timer.time()? - start?)
+}
+
+/// Whether the segments in the range together were faster than the sum of
+/// their best segments
+fn is_gold(timer: &WlSplitTimer, segments: RangeInclusive<usize>) -> bool {
+    let mut time = 0;
+    let mut best = 0.0;
+    for index in segments {
+        match (
+            timer.get_segment_time(index),
+            timer.segment_best_time(index)[timer.timing_method()],
+        ) {
+            (Some(segment), Some(best_segment)) => {
+                time += segment;
+                best += best_segment.total_milliseconds();
+            }
+            _ => return false,
+        }
+    }
+    (time as f64) < best
+}
+
+fn diff_time(
+    time: Option<TimeSpan>,
+    comparison: Option<TimeSpan>,
+    gold: bool,
+) -> Option<(String, Color)> {
+    let time = time?.to_duration().num_milliseconds();
+    let comparison = comparison?.to_duration().num_milliseconds();
+    let negative = comparison > time;
+    let diff = if negative {
+        comparison - time
+    } else {
+        time - comparison
+    } as u128;
+    let color = if gold {
+        Color::Gold
+    } else if negative {
+        Color::Gain
+    } else {
+        Color::Loss
+    };
+    Some((TimeFormat::for_diff().format_time(diff, negative), color))
+}
+
+fn format_time(time: Option<TimeSpan>) -> String {
+    time.map_or("-".to_string(), |time| {
+        TimeFormat::default().format_time_span(time)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::wl_split_timer::tests::{remove, timer};
+
+    fn layout(visible_splits: usize, upcoming_splits: usize) -> Layout {
+        let config = Config {
+            visible_splits,
+            upcoming_splits,
+            ..Default::default()
+        };
+        Layout::new(&config).unwrap()
+    }
+
+    #[test]
+    fn shows_all_splits_if_they_fit() {
+        assert_eq!(layout(0, 1).visible_split_indices(3, 1), [0, 1, 2]);
+        assert_eq!(layout(5, 1).visible_split_indices(3, 1), [0, 1, 2]);
+    }
+
+    #[test]
+    fn scrolls_to_the_current_split() {
+        let layout = layout(5, 1);
+        assert_eq!(layout.visible_split_indices(10, 0), [0, 1, 2, 3, 9]);
+        assert_eq!(layout.visible_split_indices(10, 2), [0, 1, 2, 3, 9]);
+        assert_eq!(layout.visible_split_indices(10, 5), [3, 4, 5, 6, 9]);
+        assert_eq!(layout.visible_split_indices(10, 8), [5, 6, 7, 8, 9]);
+        assert_eq!(layout.visible_split_indices(10, 9), [5, 6, 7, 8, 9]);
+    }
+
+    #[test]
+    fn keeps_upcoming_splits_in_view() {
+        assert_eq!(layout(5, 3).visible_split_indices(10, 1), [1, 2, 3, 4, 9]);
+        // More upcoming splits than fit leave the current one at the top
+        assert_eq!(layout(3, 5).visible_split_indices(10, 4), [4, 5, 9]);
+    }
+
+    #[test]
+    fn shows_only_the_current_split_in_a_single_row() {
+        let layout = layout(1, 1);
+        assert_eq!(layout.visible_split_indices(10, 0), [0]);
+        assert_eq!(layout.visible_split_indices(10, 9), [9]);
+    }
+
+    fn rows(names: &[&str], current: usize) -> Vec<(usize, usize, String, bool)> {
+        let segments: Vec<Segment> = names.iter().map(|name| Segment::new(*name)).collect();
+        split_rows(&segments, current)
+            .into_iter()
+            .map(|row| (row.index, row.first, row.name, row.header))
+            .collect()
+    }
+
+    fn row(index: usize, first: usize, name: &str, header: bool) -> (usize, usize, String, bool) {
+        (index, first, name.to_string(), header)
+    }
+
+    const GROUPED: [&str; 5] = ["a", "-b1", "-b2", "{B} b3", "c"];
+
+    #[test]
+    fn collapses_other_groups() {
+        assert_eq!(
+            rows(&GROUPED, 0),
+            [
+                row(0, 0, "a", false),
+                row(3, 1, "B", false),
+                row(4, 4, "c", false)
+            ]
+        );
+    }
+
+    #[test]
+    fn expands_the_current_group() {
+        assert_eq!(
+            rows(&GROUPED, 2),
+            [
+                row(0, 0, "a", false),
+                row(3, 1, "B", true),
+                row(1, 1, "  b1", false),
+                row(2, 2, "  b2", false),
+                row(3, 3, "  b3", false),
+                row(4, 4, "c", false),
+            ]
+        );
+    }
+
+    #[test]
+    fn ends_unfinished_groups_at_the_last_split() {
+        assert_eq!(
+            rows(&["a", "-b", "-c"], 0),
+            [row(0, 0, "a", false), row(2, 1, "c", false)]
+        );
+    }
+
+    #[test]
+    fn splits_group_names() {
+        assert_eq!(group_name("-{Group} First"), (Some("Group"), "First"));
+        assert_eq!(group_name("{Group}Last"), (Some("Group"), "Last"));
+        assert_eq!(group_name("-Subsplit"), (None, "Subsplit"));
+        assert_eq!(group_name("{Unclosed"), (None, "{Unclosed"));
+    }
+
+    fn livesplit_layout(name: &str) -> Layout {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/layouts")
+            .join(name);
+        let config = Config {
+            layout_file: Some(path.to_string_lossy().into_owned()),
+            ..Default::default()
+        };
+        Layout::new(&config).unwrap()
+    }
+
+    #[test]
+    fn reads_livesplit_layouts() {
+        let mut layout = livesplit_layout("colors.lsl");
+        let mut timer = timer("layout-livesplit");
+        let lines = layout.lines(&timer);
+        let kinds: Vec<_> = lines.iter().map(|line| line.kind).collect();
+        // The graph is left out
+        assert_eq!(
+            kinds,
+            [Kind::Title, Kind::Separator, Kind::Timer, Kind::Info]
+        );
+        assert_eq!(lines[2].value, "0.00");
+        assert_eq!(lines[2].color, Color::NotRunning);
+        assert_eq!(
+            (lines[3].text.as_str(), lines[3].value.as_str()),
+            ("Left", "Right")
+        );
+
+        timer.start();
+        timer.pause();
+        assert_eq!(layout.lines(&timer)[2].color, Color::Paused);
+        remove(&timer);
+    }
+
+    #[test]
+    fn uses_the_colors_of_livesplit_layouts() {
+        let palette = livesplit_layout("colors.lsl").palette();
+        assert_eq!(palette.background, [0xff, 0x10, 0x18, 0x20]);
+        assert_eq!(palette.separator, [0xff, 0x80, 0x80, 0x80]);
+        let colors = [
+            (Color::Normal, [0xee, 0xee, 0xee]),
+            (Color::Gain, [0x00, 0xcc, 0x36]),
+            (Color::AheadLosing, [0x52, 0xcc, 0x73]),
+            (Color::BehindGaining, [0xcc, 0x5c, 0x52]),
+            (Color::Loss, [0xcc, 0x12, 0x00]),
+            (Color::Gold, [0xd8, 0xaf, 0x1f]),
+            (Color::PersonalBest, [0x16, 0xa6, 0xff]),
+            (Color::NotRunning, [0xac, 0xac, 0xac]),
+            (Color::Paused, [0x7a, 0x7a, 0x7a]),
+        ];
+        for (color, [r, g, b]) in colors.iter() {
+            assert_eq!(palette.color(*color), [0xff, *r, *g, *b], "{:?}", color);
+        }
+
+        let config = Config::default();
+        assert_eq!(
+            Layout::new(&config).unwrap().palette(),
+            Palette::from_config(&config)
+        );
+    }
+}
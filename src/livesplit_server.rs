@@ -0,0 +1,275 @@
+use std::{
+    error::Error,
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use livesplit_core::{
+    analysis::current_pace, comparison::best_segments, TimeSpan, TimerPhase, TimingMethod,
+};
+
+use crate::{time_format::TimeFormat, wl_split_timer::WlSplitTimer};
+
+/// Accepts connections speaking LiveSplit Server's text protocol on
+/// localhost, so tools written for LiveSplit can control the timer
+pub fn spawn(timer: Arc<Mutex<WlSplitTimer>>, port: u16) -> Result<(), Box<dyn Error>> {
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let timer = Arc::clone(&timer);
+            thread::spawn(move || handle_stream(&timer, stream));
+        }
+    });
+    Ok(())
+}
+
+fn handle_stream(timer: &Mutex<WlSplitTimer>, stream: TcpStream) {
+    let mut writer = match stream.try_clone() {
+        Ok(writer) => writer,
+        Err(_) => return,
+    };
+    for line in BufReader::new(stream).lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => return,
+        };
+        let line = line.trim();
+        let (command, argument) = line.split_once(' ').unwrap_or((line, ""));
+        let response = execute(&mut timer.lock().unwrap(), command, argument.trim());
+        if let Some(response) = response {
+            if write!(writer, "{}\r\n", response).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Runs a single command, only the `get` commands and `ping` are answered.
+/// Like in LiveSplit unknown commands and invalid arguments are ignored.
+fn execute(timer: &mut WlSplitTimer, command: &str, argument: &str) -> Option<String> {
+    let phase = timer.timer().current_phase();
+    let method = timer.timing_method();
+    let index = timer.current_segment_index();
+    let comparison = match argument {
+        "" => timer.comparison().to_string(),
+        comparison => comparison.to_string(),
+    };
+    match command {
+        "starttimer" => timer.start(),
+        "startorsplit" if phase == TimerPhase::NotRunning => timer.start(),
+        "startorsplit" | "split" => report(timer.split()),
+        "unsplit" => timer.undo(),
+        "skipsplit" => timer.skip(),
+        "pause" if phase == TimerPhase::Running => timer.pause(),
+        "resume" if phase == TimerPhase::Paused => timer.pause(),
+        "reset" => report(timer.reset(true)),
+        // Game time is initialized whenever the timer starts
+        "initgametime" => {}
+        "setgametime" => {
+            if let Ok(time) = argument.parse() {
+                timer.set_game_time(time);
+            }
+        }
+        "setloadingtimes" => {
+            if let Ok(time) = argument.parse() {
+                timer.set_loading_times(time);
+            }
+        }
+        "pausegametime" => timer.pause_game_time(),
+        "unpausegametime" => timer.resume_game_time(),
+        "setcomparison" => {
+            timer.set_comparison(argument).ok();
+        }
+        "switchto" => match argument {
+            "realtime" => timer.set_timing_method(TimingMethod::RealTime),
+            "gametime" => timer.set_timing_method(TimingMethod::GameTime),
+            _ => {}
+        },
+        "getdelta" => return Some(format_delta(timer.last_delta(&comparison))),
+        "getlastsplittime" => {
+            let time = index
+                .and_then(|index| index.checked_sub(1))
+                .and_then(|index| timer.segments()[index].split_time()[method]);
+            return Some(format_time(time));
+        }
+        "getcomparisonsplittime" => {
+            let time = timer
+                .current_segment()
+                .and_then(|segment| segment.comparison(&comparison)[method]);
+            return Some(format_time(time));
+        }
+        "getcurrenttime" => {
+            // Game time is only there once it was initialized
+            let time = timer
+                .time()
+                .or_else(|| timer.time_with_method(TimingMethod::RealTime));
+            return Some(format_time(time));
+        }
+        "getfinaltime" => {
+            let last = timer.segments().last()?;
+            let time = match phase {
+                TimerPhase::Ended => last.split_time()[method],
+                _ => last.comparison(&comparison)[method],
+            };
+            return Some(format_time(time));
+        }
+        "getpredictedtime" => {
+            return Some(format_time(current_pace::calculate(
+                timer.timer(),
+                &comparison,
+            )));
+        }
+        "getbestpossibletime" => {
+            return Some(format_time(current_pace::calculate(
+                timer.timer(),
+                best_segments::NAME,
+            )));
+        }
+        "getsplitindex" => {
+            return Some(index.map_or(-1, |index| index as i64).to_string());
+        }
+        "getcurrentsplitname" => {
+            return Some(name(timer, index));
+        }
+        "getprevioussplitname" => {
+            return Some(name(timer, index.and_then(|index| index.checked_sub(1))));
+        }
+        "getcurrenttimerphase" => return Some(format!("{:?}", phase)),
+        "ping" => return Some("pong".to_string()),
+        _ => {}
+    }
+    None
+}
+
+/// There is no reply to these commands, so errors only go to stderr
+fn report(result: Result<(), String>) {
+    if let Err(e) = result {
+        eprintln!("{}", e);
+    }
+}
+
+fn name(timer: &WlSplitTimer, index: Option<usize>) -> String {
+    index
+        .and_then(|index| timer.segments().get(index))
+        .map_or_else(|| "-".to_string(), |segment| segment.name().to_string())
+}
+
+fn format_time(time: Option<TimeSpan>) -> String {
+    time.map_or_else(
+        || "-".to_string(),
+        |time| TimeFormat::for_file().format_time_span(time),
+    )
+}
+
+fn format_delta(delta: Option<TimeSpan>) -> String {
+    delta.map_or_else(
+        || "-".to_string(),
+        |delta| TimeFormat::for_diff().format_time_span(delta),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wl_split_timer::tests::{remove, timer};
+
+    fn reply(timer: &mut WlSplitTimer, line: &str) -> Option<String> {
+        let (command, argument) = line.split_once(' ').unwrap_or((line, ""));
+        execute(timer, command, argument)
+    }
+
+    #[test]
+    fn answers_before_the_start() {
+        let mut timer = timer("livesplit-server-idle");
+        assert_eq!(reply(&mut timer, "ping").as_deref(), Some("pong"));
+        assert_eq!(reply(&mut timer, "getsplitindex").as_deref(), Some("-1"));
+        assert_eq!(reply(&mut timer, "getdelta").as_deref(), Some("-"));
+        assert_eq!(reply(&mut timer, "getlastsplittime").as_deref(), Some("-"));
+        assert_eq!(
+            reply(&mut timer, "getcurrentsplitname").as_deref(),
+            Some("-")
+        );
+        assert_eq!(
+            reply(&mut timer, "getcurrenttime").as_deref(),
+            Some("00:00:00.000")
+        );
+        assert_eq!(
+            reply(&mut timer, "getcurrenttimerphase").as_deref(),
+            Some("NotRunning")
+        );
+        assert_eq!(reply(&mut timer, "unknown"), None);
+        remove(&timer);
+    }
+
+    #[test]
+    fn controls_the_timer() {
+        let mut timer = timer("livesplit-server");
+        // A personal best of one minute per split in game time
+        assert_eq!(reply(&mut timer, "switchto gametime"), None);
+        for line in [
+            "starttimer",
+            "pausegametime",
+            "setgametime 00:01:00.000",
+            "split",
+            "setgametime 00:02:00.000",
+            "split",
+            "setgametime 00:03:00.000",
+            "split",
+        ] {
+            assert_eq!(reply(&mut timer, line), None);
+        }
+        assert_eq!(reply(&mut timer, "getsplitindex").as_deref(), Some("-1"));
+
+        reply(&mut timer, "startorsplit");
+        reply(&mut timer, "pausegametime");
+        assert_eq!(reply(&mut timer, "getsplitindex").as_deref(), Some("0"));
+        assert_eq!(
+            reply(&mut timer, "getcurrentsplitname").as_deref(),
+            Some("a")
+        );
+        assert_eq!(reply(&mut timer, "getdelta").as_deref(), Some("-"));
+        reply(&mut timer, "setgametime 00:00:55.500");
+        assert_eq!(
+            reply(&mut timer, "getcurrenttime").as_deref(),
+            Some("00:00:55.500")
+        );
+        reply(&mut timer, "startorsplit");
+        assert_eq!(reply(&mut timer, "getsplitindex").as_deref(), Some("1"));
+        assert_eq!(
+            reply(&mut timer, "getprevioussplitname").as_deref(),
+            Some("a")
+        );
+        assert_eq!(
+            reply(&mut timer, "getlastsplittime").as_deref(),
+            Some("00:00:55.500")
+        );
+        assert_eq!(reply(&mut timer, "getdelta").as_deref(), Some("-04.500"));
+        assert_eq!(
+            reply(&mut timer, "getcomparisonsplittime").as_deref(),
+            Some("00:02:00.000")
+        );
+        assert_eq!(
+            reply(&mut timer, "getfinaltime").as_deref(),
+            Some("00:03:00.000")
+        );
+
+        reply(&mut timer, "unsplit");
+        assert_eq!(reply(&mut timer, "getsplitindex").as_deref(), Some("0"));
+        reply(&mut timer, "skipsplit");
+        assert_eq!(reply(&mut timer, "getsplitindex").as_deref(), Some("1"));
+        reply(&mut timer, "pause");
+        assert_eq!(
+            reply(&mut timer, "getcurrenttimerphase").as_deref(),
+            Some("Paused")
+        );
+        reply(&mut timer, "resume");
+        reply(&mut timer, "reset");
+        assert_eq!(
+            reply(&mut timer, "getcurrenttimerphase").as_deref(),
+            Some("NotRunning")
+        );
+        remove(&timer);
+    }
+}
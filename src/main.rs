@@ -1,25 +1,44 @@
 use crate::{
     config::Config,
-    display::{Headless, TerminalApp, Wayland},
+    display::{Headless, ImageApp, TerminalApp, Wayland},
+    journal::Recovery,
+    layout::Layout,
+    overlay::Overlay,
     wl_split_timer::RunMetadata,
 };
-use clap::{App, Arg};
+use clap::{App, AppSettings, Arg, SubCommand};
+use livesplit_core::TimingMethod;
+use protocol::{Request, Response};
 use std::{
     env,
     error::Error,
     fs::OpenOptions,
+    path::Path,
     sync::{Arc, Mutex},
     time::Duration,
 };
 use std::{
-    io::{BufRead, BufReader},
+    io::{BufRead, BufReader, Write},
     os::unix::net::{UnixListener, UnixStream},
 };
 use wl_split_timer::WlSplitTimer;
 mod config;
+#[cfg(feature = "dbus")]
+mod dbus;
 mod display;
+mod editor;
+mod event;
 mod file;
+#[cfg(feature = "hotkeys")]
+mod hotkeys;
+mod journal;
+mod layout;
+mod livesplit_server;
+mod overlay;
+mod protocol;
+mod stats;
 mod time_format;
+mod watch;
 mod wl_split_timer;
 
 #[macro_export]
@@ -34,6 +53,9 @@ const SOCKET_NAME: &str = concat!(app_name!(), ".sock");
 pub trait TimerDisplay {
     fn run(&mut self) -> Result<bool, Box<dyn Error>>;
 
+    /// Applies a reloaded config, called while no attempt is running
+    fn reload(&mut self, config: &Config, layout: Layout) -> Result<(), Box<dyn Error>>;
+
     fn timer(&self) -> &Arc<Mutex<WlSplitTimer>>;
 }
 
@@ -44,6 +66,7 @@ fn main() -> Result<(), Box<dyn Error>> {
         SOCKET_NAME
     );
     let matches = App::new("wlsplit")
+        .setting(AppSettings::SubcommandsNegateReqs)
         .arg(Arg::with_name("file").required(true).index(1))
         .arg(
             Arg::with_name("display")
@@ -51,6 +74,13 @@ fn main() -> Result<(), Box<dyn Error>> {
                 .long("display")
                 .default_value("wayland"),
         )
+        .arg(
+            Arg::with_name("image")
+                .long("image")
+                .help("PNG file written by the image display, defaults to the input file with a .png extension")
+                .required(false)
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("create_file")
                 .short("f")
@@ -80,14 +110,99 @@ fn main() -> Result<(), Box<dyn Error>> {
                 .required(false)
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("export")
+                .long_help("Writes the run to the given file and exits, a .lss extension exports LiveSplit splits")
+                .long("export")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("recover")
+                .long_help("What to do with an attempt interrupted by a crash, asks on startup by default and commits it to history if there is no terminal to ask on")
+                .long("recover")
+                .possible_values(&["ask", "resume", "commit", "discard"])
+                .default_value("ask"),
+        )
+        .arg(
+            Arg::with_name("layout")
+                .long("layout")
+                .help("LiveSplit layout (.lsl) to display")
+                .required(false)
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("socket")
                 .short("s")
                 .long("socket")
                 .default_value(&socket_path),
         )
+        .subcommand(
+            SubCommand::with_name("edit")
+                .about("Edits segments, times and history of a splits file")
+                .arg(Arg::with_name("file").required(true).index(1)),
+        )
+        .subcommand(
+            SubCommand::with_name("export-csv")
+                .about("Writes one CSV row per attempt and segment of a splits file's history")
+                .arg(Arg::with_name("file").required(true).index(1))
+                .arg(Arg::with_name("output").required(true).index(2))
+                .arg(
+                    Arg::with_name("timing_method")
+                        .long("timing-method")
+                        .help("Timing method of the exported times, defaults to the configured one")
+                        .possible_values(&["real", "game"])
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("complete_only")
+                        .long("complete-only")
+                        .help("Leaves out attempts that were reset"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("stats")
+                .about("Reports statistics of the attempt and segment history of a splits file")
+                .arg(Arg::with_name("file").required(true).index(1))
+                .arg(
+                    Arg::with_name("json")
+                        .long("json")
+                        .help("Prints the statistics as JSON"),
+                ),
+        )
         .get_matches();
-    let config: Config = confy::load("wlsplit")?;
+    let layout_file = matches.value_of("layout");
+    let config = load_config(layout_file)?;
+    if let Some(matches) = matches.subcommand_matches("edit") {
+        let file = matches.value_of("file").unwrap();
+        if let Err(e) = editor::edit(load(file), &config) {
+            eprintln!("Unable to edit {}: {}", file, e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    if let Some(matches) = matches.subcommand_matches("export-csv") {
+        let timer = load(matches.value_of("file").unwrap());
+        let method = match matches.value_of("timing_method") {
+            Some("real") => TimingMethod::RealTime,
+            Some("game") => TimingMethod::GameTime,
+            _ => config.timing_method,
+        };
+        let output = matches.value_of("output").unwrap();
+        if let Err(e) = timer.export_csv(output, method, matches.is_present("complete_only")) {
+            eprintln!("Unable to export {}: {}", output, e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    if let Some(matches) = matches.subcommand_matches("stats") {
+        let file = matches.value_of("file").unwrap();
+        if let Err(e) = stats::stats(&load(file), matches.is_present("json"), &config) {
+            eprintln!("Unable to report statistics of {}: {}", file, e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
     println!("{:?}", config);
     let input = matches.value_of("file").expect("Input file required!");
 
@@ -100,7 +215,7 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     let socket = matches.value_of("socket").unwrap().to_string();
 
-    let timer = if create_file {
+    let mut timer = if create_file {
         let metadata = RunMetadata {
             game_name: matches.value_of("game_name"),
             category_name: matches.value_of("category_name"),
@@ -108,73 +223,207 @@ fn main() -> Result<(), Box<dyn Error>> {
                 .value_of("splits")
                 .map(|split_names| split_names.split(',').collect()),
         };
-        WlSplitTimer::new(input.to_string(), metadata)
+        match WlSplitTimer::new(input.to_string(), metadata) {
+            Ok(timer) => timer,
+            Err(e) => {
+                eprintln!("Unable to create {}: {}", input, e);
+                std::process::exit(1);
+            }
+        }
     } else {
-        WlSplitTimer::from_file(input.to_string())
+        load(input)
+    };
+    apply_config(&mut timer, &config);
+
+    if let Some(export) = matches.value_of("export") {
+        return timer.export(export);
+    }
+
+    if !create_file {
+        timer.recover(matches.value_of("recover").unwrap().parse::<Recovery>()?);
+    }
+
+    let layout = match Layout::new(&config) {
+        Ok(layout) => layout,
+        Err(e) => {
+            eprintln!("Unable to load layout: {}", e);
+            std::process::exit(1);
+        }
     };
 
     let display = matches.value_of("display").unwrap();
-    let app = get_app(display, timer, &config);
+    let image = matches.value_of("image").map_or_else(
+        || {
+            Path::new(input)
+                .with_extension("png")
+                .to_string_lossy()
+                .into_owned()
+        },
+        String::from,
+    );
+    let mut app = get_app(display, timer, layout, &config, image)?;
 
-    let app = Arc::new(Mutex::new(app));
-    let timer = Arc::clone(app.lock().unwrap().timer());
+    if config.watch_files {
+        if let Err(e) = watch::spawn(Arc::clone(app.timer())) {
+            eprintln!("Unable to watch files: {}", e);
+        }
+    }
+    let overlay = config.overlay_port.and_then(|port| {
+        overlay::spawn(Arc::clone(app.timer()), &config, port)
+            .map_err(|e| eprintln!("Unable to start the overlay: {}", e))
+            .ok()
+    });
+    #[cfg(feature = "dbus")]
+    if let Err(e) = dbus::spawn(Arc::clone(app.timer())) {
+        eprintln!("Unable to register on D-Bus: {}", e);
+    }
+    #[cfg(feature = "hotkeys")]
+    if !config.hotkeys.is_empty() {
+        if let Err(e) = hotkeys::spawn(Arc::clone(app.timer()), &config.hotkeys) {
+            eprintln!("Unable to start the hotkeys: {}", e);
+        }
+    }
+    #[cfg(not(feature = "hotkeys"))]
+    if !config.hotkeys.is_empty() {
+        eprintln!("Hotkeys are configured, but wlsplit was built without the hotkeys feature");
+    }
+    if let Some(port) = config.livesplit_server_port {
+        if let Err(e) = livesplit_server::spawn(Arc::clone(app.timer()), port) {
+            eprintln!("Unable to start the LiveSplit server: {}", e);
+        }
+    }
+
+    let timer = Arc::clone(app.timer());
 
     std::fs::remove_file(&socket).ok();
     let listener = UnixListener::bind(&socket).unwrap();
     std::thread::spawn(move || {
         for stream in listener.incoming().flatten() {
-            if handle_stream_response(&timer, stream) {
-                break;
-            }
+            let timer = Arc::clone(&timer);
+            std::thread::spawn(move || handle_stream_response(&timer, stream));
         }
     });
 
     loop {
-        if app.lock().unwrap().run().unwrap_or(false) {
+        if app.run().unwrap_or(false) {
             break;
         }
+        if let Err(e) = reload_config(app.as_mut(), overlay.as_ref(), layout_file) {
+            eprintln!("Unable to reload config: {}", e);
+        }
         std::thread::sleep(Duration::from_millis(33));
     }
     std::fs::remove_file(&socket).ok();
     Ok(())
 }
 
-fn handle_stream_response(timer: &Arc<Mutex<WlSplitTimer>>, stream: UnixStream) -> bool {
+/// Reads a splits file, exits with the error if it can't be read
+fn load(file: &str) -> WlSplitTimer {
+    match WlSplitTimer::from_file(file.to_string()) {
+        Ok(timer) => timer,
+        Err(e) => {
+            eprintln!("Unable to load {}: {}", file, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Loads the config, a layout passed on the command line replaces the configured one
+fn load_config(layout_file: Option<&str>) -> Result<Config, Box<dyn Error>> {
+    let mut config: Config = confy::load("wlsplit")?;
+    if let Some(layout_file) = layout_file {
+        config.layout_file = Some(layout_file.to_string());
+    }
+    Ok(config)
+}
+
+fn apply_config(timer: &mut WlSplitTimer, config: &Config) {
+    timer.set_timing_method(config.timing_method);
+    timer.set_backups(config.backups);
+    if let Err(e) = timer.set_comparison(&config.comparison) {
+        eprintln!("{}", e);
+    }
+}
+
+/// Re-reads the config once a reload was requested and no attempt is running,
+/// the previous config stays in use if the new one can't be loaded
+fn reload_config(
+    app: &mut dyn TimerDisplay,
+    overlay: Option<&Overlay>,
+    layout_file: Option<&str>,
+) -> Result<(), Box<dyn Error>> {
+    let timer = Arc::clone(app.timer());
+    let mut timer = timer.lock().unwrap();
+    if !timer.config_reload_pending() {
+        return Ok(());
+    }
+    timer.reload_config = false;
+    let config = load_config(layout_file)?;
+    let layout = Layout::new(&config)?;
+    apply_config(&mut timer, &config);
+    drop(timer);
+    if let Some(overlay) = overlay {
+        overlay.reload(&config)?;
+    }
+    app.reload(&config, layout)
+}
+
+fn handle_stream_response(timer: &Arc<Mutex<WlSplitTimer>>, stream: UnixStream) {
+    let mut writer = match stream.try_clone() {
+        Ok(writer) => writer,
+        Err(_) => return,
+    };
     let stream = BufReader::new(stream);
     for line in stream.lines() {
-        match line.unwrap_or_default().as_str() {
-            "start" => {
-                timer.lock().unwrap().start();
-            }
-            "split" => {
-                timer.lock().unwrap().split();
-            }
-            "skip" => {
-                timer.lock().unwrap().skip();
-            }
-            "pause" => {
-                timer.lock().unwrap().pause();
-            }
-            "reset" => {
-                timer.lock().unwrap().reset(true);
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => return,
+        };
+        let request = Request::parse(&line);
+        // Subscribed before the reply, so no event after it can be missed
+        let events = match &request {
+            Ok(request) if request.cmd == "subscribe" => Some(timer.lock().unwrap().subscribe()),
+            _ => None,
+        };
+        let response = match request {
+            Ok(_) if events.is_some() => Response::ok(),
+            Ok(request) => protocol::execute(timer, &request),
+            Err(e) => Response::error(e),
+        };
+        if Request::is_json(&line) {
+            if let Ok(response) = serde_json::to_string(&response) {
+                writeln!(writer, "{}", response).ok();
             }
-            "quit" => {
-                timer.lock().unwrap().quit();
-                return true;
+        }
+        if let Some(events) = events {
+            // Only the subscriber waits on the channel, the timer never blocks
+            // on it. Once the client is gone the receiver is dropped here and
+            // the timer forgets the subscriber on its next event.
+            for event in events {
+                let event = serde_json::to_string(&event).unwrap_or_default();
+                if writeln!(writer, "{}", event).is_err() {
+                    break;
+                }
             }
-            _ => {}
+            return;
         }
     }
-    false
 }
 
-fn get_app(display: &str, timer: WlSplitTimer, config: &Config) -> Box<dyn TimerDisplay> {
-    match display {
-        "terminal" => Box::new(TerminalApp::new(timer)),
+fn get_app(
+    display: &str,
+    timer: WlSplitTimer,
+    layout: Layout,
+    config: &Config,
+    image: String,
+) -> Result<Box<dyn TimerDisplay>, Box<dyn Error>> {
+    Ok(match display {
+        "terminal" => Box::new(TerminalApp::new(timer, layout, config)?),
         "null" => Box::new(Headless::new(timer)),
-        "wayland" => Box::new(Wayland::new(timer, config)),
+        "wayland" => Box::new(Wayland::new(timer, layout, config)),
+        "image" => Box::new(ImageApp::new(timer, layout, config, image)?),
         _ => {
             panic!("Unknown method");
         }
-    }
+    })
 }
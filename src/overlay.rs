@@ -0,0 +1,253 @@
+use std::{
+    error::Error,
+    fs,
+    io::{Read, Write},
+    mem,
+    net::{TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use serde::Serialize;
+use tungstenite::{Message, WebSocket};
+
+use crate::{
+    config::Config,
+    layout::{Layout, Line},
+    protocol::State,
+    wl_split_timer::WlSplitTimer,
+};
+
+const PAGE: &str = include_str!("overlay.html");
+
+/// Sent to the overlay page whenever the displayed timer changes
+#[derive(Serialize)]
+struct Update<'a> {
+    state: State,
+    lines: &'a [Line],
+}
+
+/// Connected overlay pages and the last update they were sent
+#[derive(Default)]
+struct Feed {
+    clients: Vec<WebSocket<TcpStream>>,
+    last: String,
+}
+
+impl Feed {
+    /// Updates are only sent on changes, a new page needs the current one.
+    /// Sent outside the lock, so it is sent again if an update came in between.
+    fn add(feed: &Mutex<Feed>, mut client: WebSocket<TcpStream>) {
+        let mut sent = String::new();
+        loop {
+            let mut locked = feed.lock().unwrap();
+            if locked.last == sent {
+                locked.clients.push(client);
+                return;
+            }
+            let last = locked.last.clone();
+            drop(locked);
+            if client.send(Message::Text(last.clone())).is_err() {
+                return;
+            }
+            sent = last;
+        }
+    }
+
+    /// The clients are taken out while sending, so that a page that is slow
+    /// to read does not hold up new connections
+    fn send(feed: &Mutex<Feed>, update: String) {
+        let mut clients = {
+            let mut locked = feed.lock().unwrap();
+            if update == locked.last {
+                return;
+            }
+            locked.last = update.clone();
+            mem::take(&mut locked.clients)
+        };
+        clients.retain_mut(|client| client.send(Message::Text(update.clone())).is_ok());
+        let mut locked = feed.lock().unwrap();
+        // Pages added in the meantime already got this update
+        clients.append(&mut locked.clients);
+        locked.clients = clients;
+    }
+}
+
+/// The running overlay, which follows reloads of the config
+pub struct Overlay {
+    layout: Arc<Mutex<Layout>>,
+    css: Arc<Mutex<Option<String>>>,
+}
+
+impl Overlay {
+    /// Applies a reloaded config, the port stays the same until a restart
+    pub fn reload(&self, config: &Config) -> Result<(), Box<dyn Error>> {
+        *self.layout.lock().unwrap() = Layout::new(config)?;
+        *self.css.lock().unwrap() = config.overlay_css.clone();
+        Ok(())
+    }
+}
+
+/// Serves the overlay page on localhost, for browser sources in streaming
+/// software. The page gets the lines of the layout through a WebSocket at `/ws`.
+pub fn spawn(
+    timer: Arc<Mutex<WlSplitTimer>>,
+    config: &Config,
+    port: u16,
+) -> Result<Overlay, Box<dyn Error>> {
+    serve(timer, config, TcpListener::bind(("127.0.0.1", port))?)
+}
+
+fn serve(
+    timer: Arc<Mutex<WlSplitTimer>>,
+    config: &Config,
+    listener: TcpListener,
+) -> Result<Overlay, Box<dyn Error>> {
+    let overlay = Overlay {
+        layout: Arc::new(Mutex::new(Layout::new(config)?)),
+        css: Arc::new(Mutex::new(config.overlay_css.clone())),
+    };
+    let feed = Arc::new(Mutex::new(Feed::default()));
+
+    let connected = Arc::clone(&feed);
+    let css = Arc::clone(&overlay.css);
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let feed = Arc::clone(&connected);
+            let css = css.lock().unwrap().clone();
+            thread::spawn(move || {
+                if let Err(e) = handle_request(stream, css.as_deref(), &feed) {
+                    eprintln!("Overlay request failed: {}", e);
+                }
+            });
+        }
+    });
+
+    let interval = Duration::from_millis((1000 / config.target_framerate).into());
+    let layout = Arc::clone(&overlay.layout);
+    thread::spawn(move || loop {
+        thread::sleep(interval);
+        {
+            let mut feed = feed.lock().unwrap();
+            if feed.clients.is_empty() {
+                feed.last.clear();
+                continue;
+            }
+        }
+        let timer = timer.lock().unwrap();
+        let lines = layout.lock().unwrap().lines(&timer);
+        let update = Update {
+            state: State::new(&timer),
+            lines: &lines,
+        };
+        drop(timer);
+        match serde_json::to_string(&update) {
+            Ok(update) => Feed::send(&feed, update),
+            Err(e) => eprintln!("Unable to serialize overlay update: {}", e),
+        }
+    });
+    Ok(overlay)
+}
+
+fn handle_request(
+    mut stream: TcpStream,
+    css: Option<&str>,
+    feed: &Mutex<Feed>,
+) -> Result<(), Box<dyn Error>> {
+    // A page that stopped reading must not hold up the others
+    stream.set_write_timeout(Some(Duration::from_secs(1)))?;
+    // The WebSocket handshake reads the request itself, so it is only peeked at
+    let mut request = [0; 1024];
+    let len = stream.peek(&mut request)?;
+    let request = String::from_utf8_lossy(&request[..len]).into_owned();
+    let path = request.split_whitespace().nth(1).unwrap_or("/");
+    if path == "/ws" {
+        let client = tungstenite::accept(stream).map_err(|e| e.to_string())?;
+        Feed::add(feed, client);
+        return Ok(());
+    }
+
+    read_request(&mut stream)?;
+    match path {
+        "/" => respond(&mut stream, "200 OK", "text/html", PAGE),
+        "/theme.css" => {
+            let theme = match css {
+                Some(css) => fs::read_to_string(css)?,
+                None => String::new(),
+            };
+            respond(&mut stream, "200 OK", "text/css", &theme)
+        }
+        _ => respond(&mut stream, "404 Not Found", "text/plain", "Not found"),
+    }
+}
+
+/// Reads the request up to the end of its headers, requests to the overlay
+/// have no body
+fn read_request(stream: &mut TcpStream) -> Result<(), Box<dyn Error>> {
+    let mut request = Vec::new();
+    let mut buffer = [0; 1024];
+    while !request.windows(4).any(|end| end == b"\r\n\r\n") && request.len() < 16384 {
+        let len = stream.read(&mut buffer)?;
+        if len == 0 {
+            break;
+        }
+        request.extend_from_slice(&buffer[..len]);
+    }
+    Ok(())
+}
+
+fn respond(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &str,
+) -> Result<(), Box<dyn Error>> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        layout::Component,
+        wl_split_timer::tests::{remove, timer},
+    };
+
+    fn lines(socket: &mut WebSocket<TcpStream>) -> usize {
+        let update = socket.read().unwrap().into_text().unwrap();
+        let update: serde_json::Value = serde_json::from_str(&update).unwrap();
+        update["lines"].as_array().unwrap().len()
+    }
+
+    #[test]
+    fn follows_reloaded_layouts() {
+        let timer = Arc::new(Mutex::new(timer("overlay")));
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let address = listener.local_addr().unwrap();
+        let mut config = Config::default();
+        let overlay = serve(Arc::clone(&timer), &config, listener).unwrap();
+
+        let stream = TcpStream::connect(address).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let url = format!("ws://{}/ws", address);
+        let (mut socket, _) = tungstenite::client(url, stream).unwrap();
+        // The title, three splits, the timer and three more components
+        assert_eq!(lines(&mut socket), 8);
+
+        config.layout = vec![Component::Timer];
+        overlay.reload(&config).unwrap();
+        assert_eq!(lines(&mut socket), 1);
+        remove(&timer.lock().unwrap());
+    }
+}
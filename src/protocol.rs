@@ -0,0 +1,370 @@
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use livesplit_core::{TimeSpan, TimingMethod};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    time_format::TimeFormat,
+    wl_split_timer::{RunMetadata, WlSplitTimer},
+};
+
+pub const PROTOCOL_VERSION: u32 = 1;
+
+#[derive(Debug, Deserialize, PartialEq)]
+pub struct Request {
+    pub cmd: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    pub version: Option<u32>,
+}
+
+impl Request {
+    /// Parses either a JSON request or a plain text command line.
+    pub fn parse(line: &str) -> Result<Self, String> {
+        if Request::is_json(line) {
+            return serde_json::from_str(line).map_err(|e| format!("Invalid request: {}", e));
+        }
+        let mut words = line.split_whitespace().map(String::from);
+        let cmd = words.next().ok_or("Empty request")?;
+        Ok(Self {
+            cmd,
+            args: words.collect(),
+            version: None,
+        })
+    }
+
+    /// Plain text commands are fire-and-forget, only JSON requests get a reply
+    pub fn is_json(line: &str) -> bool {
+        line.trim_start().starts_with('{')
+    }
+}
+
+#[derive(Debug, Serialize, Default)]
+pub struct Response {
+    pub version: u32,
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(flatten)]
+    pub state: Option<State>,
+    #[serde(flatten)]
+    pub times: Option<Times>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub splits: Option<Vec<Split>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backups: Option<Vec<String>>,
+}
+
+impl Response {
+    pub fn ok() -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            ok: true,
+            ..Default::default()
+        }
+    }
+
+    pub fn error(error: String) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            ok: false,
+            error: Some(error),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct State {
+    pub phase: String,
+    pub timing_method: TimingMethod,
+    pub comparison: String,
+    pub game_name: String,
+    pub category_name: String,
+    pub attempt_count: u32,
+    pub current_split_index: Option<usize>,
+    pub current_split: Option<String>,
+    #[serde(flatten)]
+    pub times: Times,
+}
+
+impl State {
+    pub fn new(timer: &WlSplitTimer) -> Self {
+        Self {
+            phase: format!("{:?}", timer.timer().current_phase()),
+            timing_method: timer.timing_method(),
+            comparison: timer.comparison().to_string(),
+            game_name: timer.game_name().to_string(),
+            category_name: timer.category_name().to_string(),
+            attempt_count: timer.run().attempt_count(),
+            current_split_index: timer.current_segment_index(),
+            current_split: timer
+                .current_segment()
+                .map(|segment| segment.name().to_string()),
+            times: Times::new(timer),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Times {
+    pub current_time: Option<String>,
+    pub real_time: Option<String>,
+    pub game_time: Option<String>,
+}
+
+impl Times {
+    pub fn new(timer: &WlSplitTimer) -> Self {
+        Self {
+            current_time: timer.time().map(format_time),
+            real_time: timer
+                .time_with_method(TimingMethod::RealTime)
+                .map(format_time),
+            game_time: timer
+                .time_with_method(TimingMethod::GameTime)
+                .map(format_time),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Split {
+    pub name: String,
+    pub split_time: Option<String>,
+    pub personal_best_split_time: Option<String>,
+    pub comparison_time: Option<String>,
+    pub best_segment_time: Option<String>,
+}
+
+impl Split {
+    pub fn list(timer: &WlSplitTimer) -> Vec<Self> {
+        let method = timer.timing_method();
+        timer
+            .segments()
+            .iter()
+            .enumerate()
+            .map(|(index, segment)| Self {
+                name: segment.name().to_string(),
+                split_time: segment.split_time()[method].map(format_time),
+                personal_best_split_time: segment.personal_best_split_time()[method]
+                    .map(format_time),
+                comparison_time: timer.comparison_split_time(index).map(format_time),
+                best_segment_time: segment.best_segment_time()[method].map(format_time),
+            })
+            .collect()
+    }
+}
+
+/// Runs a single request against the timer, shared by all control interfaces.
+pub fn execute(timer: &Arc<Mutex<WlSplitTimer>>, request: &Request) -> Response {
+    if request.version.unwrap_or(PROTOCOL_VERSION) > PROTOCOL_VERSION {
+        return Response::error(format!(
+            "Unsupported protocol version {}, expected at most {}",
+            request.version.unwrap_or_default(),
+            PROTOCOL_VERSION
+        ));
+    }
+    let mut timer = timer.lock().unwrap();
+    match request.cmd.as_str() {
+        "start" => timer.start(),
+        "split" => {
+            if let Err(e) = timer.split() {
+                return Response::error(e);
+            }
+        }
+        "skip" => timer.skip(),
+        "pause" => timer.pause(),
+        "undo" => timer.undo(),
+        "undo-skip" => timer.undo_skip(),
+        "redo" => timer.redo(),
+        "pause-game-time" => timer.pause_game_time(),
+        "resume-game-time" => timer.resume_game_time(),
+        "set-game-time" => match request.args.first().map(|time| time.parse::<TimeSpan>()) {
+            Some(Ok(time)) => timer.set_game_time(time),
+            Some(Err(_)) => return Response::error("Invalid time".to_string()),
+            None => return Response::error("Missing time argument".to_string()),
+        },
+        "toggle-timing-method" => timer.toggle_timing_method(),
+        "next-comparison" => timer.next_comparison(),
+        "previous-comparison" => timer.previous_comparison(),
+        "set-comparison" => {
+            if let Err(e) = timer.set_comparison(&request.args.join(" ")) {
+                return Response::error(e);
+            }
+        }
+        "export" => match request.args.first() {
+            Some(path) => {
+                let result = absolute(path).and_then(|path| {
+                    timer
+                        .export(path)
+                        .map_err(|e| format!("Export failed: {}", e))
+                });
+                if let Err(e) = result {
+                    return Response::error(e);
+                }
+            }
+            None => return Response::error("Missing path argument".to_string()),
+        },
+        "restore-backup" => match request.args.first() {
+            Some(backup) => {
+                if let Err(e) = timer.restore_backup(backup) {
+                    return Response::error(e);
+                }
+            }
+            None => {
+                return Response {
+                    backups: Some(timer.list_backups()),
+                    ..Response::ok()
+                }
+            }
+        },
+        "load" => match request.args.first() {
+            Some(file) => {
+                if let Err(e) = absolute(file).and_then(|file| timer.open(file.to_string())) {
+                    return Response::error(e);
+                }
+            }
+            None => return Response::error("Missing path argument".to_string()),
+        },
+        "new" => {
+            let result = parse_new_args(&request.args)
+                .and_then(|(file, metadata)| timer.create(absolute(file)?.to_string(), metadata));
+            if let Err(e) = result {
+                return Response::error(e);
+            }
+        }
+        "reload" => {
+            if let Err(e) = timer.reload() {
+                return Response::error(e);
+            }
+        }
+        "reset" => {
+            if let Err(e) = timer.reset(true) {
+                return Response::error(e);
+            }
+        }
+        "quit" => timer.quit(),
+        "get-state" => {}
+        "get-current-time" => {
+            return Response {
+                times: Some(Times::new(&timer)),
+                ..Response::ok()
+            }
+        }
+        "get-splits" => {
+            return Response {
+                splits: Some(Split::list(&timer)),
+                ..Response::ok()
+            }
+        }
+        cmd => return Response::error(format!("Unknown command: {}", cmd)),
+    }
+    Response {
+        state: Some(State::new(&timer)),
+        ..Response::ok()
+    }
+}
+
+/// Relative paths would be resolved in the directory wlsplit was started in,
+/// not in the one of the client sending them
+fn absolute(path: &str) -> Result<&str, String> {
+    if Path::new(path).is_absolute() {
+        Ok(path)
+    } else {
+        Err(format!("Expected an absolute path: {}", path))
+    }
+}
+
+/// Parses `<path> [--game <name>] [--category <name>] [--splits <a,b,c>]`
+fn parse_new_args(args: &[String]) -> Result<(&str, RunMetadata<'_>), String> {
+    let mut file = None;
+    let mut metadata = RunMetadata {
+        game_name: None,
+        category_name: None,
+        splits: None,
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .map(String::as_str)
+                .ok_or_else(|| format!("Missing value for {}", arg))
+        };
+        match arg.as_str() {
+            "--game" => metadata.game_name = Some(value()?),
+            "--category" => metadata.category_name = Some(value()?),
+            "--splits" => metadata.splits = Some(value()?.split(',').collect()),
+            _ if file.is_none() => file = Some(arg.as_str()),
+            _ => return Err(format!("Unexpected argument: {}", arg)),
+        }
+    }
+    Ok((file.ok_or("Missing path argument")?, metadata))
+}
+
+fn format_time(time: TimeSpan) -> String {
+    TimeFormat::for_file().format_time_span(time)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wl_split_timer::tests::{remove, timer};
+
+    #[test]
+    fn parses_plain_commands() {
+        let request = Request::parse("set-game-time 1:23.456").unwrap();
+        assert_eq!(request.cmd, "set-game-time");
+        assert_eq!(request.args, ["1:23.456"]);
+        assert_eq!(request.version, None);
+        assert!(!Request::is_json("split"));
+        assert!(Request::parse("  ").is_err());
+    }
+
+    #[test]
+    fn parses_json_requests() {
+        let line = r#" {"version":1,"cmd":"set-comparison","args":["Best Segments"]}"#;
+        assert!(Request::is_json(line));
+        let request = Request::parse(line).unwrap();
+        assert_eq!(request.cmd, "set-comparison");
+        assert_eq!(request.args, ["Best Segments"]);
+        assert_eq!(request.version, Some(1));
+        assert_eq!(Request::parse(r#"{"cmd":"split"}"#).unwrap().args.len(), 0);
+        assert!(Request::parse(r#"{"version":1}"#).is_err());
+        assert!(Request::parse("{split").is_err());
+    }
+
+    #[test]
+    fn rejects_newer_versions() {
+        let timer = Arc::new(Mutex::new(timer("protocol-version")));
+        let request = Request::parse(r#"{"version":2,"cmd":"quit"}"#).unwrap();
+        let response = execute(&timer, &request);
+        assert!(!response.ok);
+        assert!(response.error.is_some());
+        assert!(!timer.lock().unwrap().exit);
+
+        let request = Request::parse(r#"{"version":1,"cmd":"quit"}"#).unwrap();
+        assert!(execute(&timer, &request).ok);
+        assert!(timer.lock().unwrap().exit);
+        remove(&timer.lock().unwrap());
+    }
+
+    #[test]
+    fn switches_only_to_absolute_paths() {
+        let timer = Arc::new(Mutex::new(timer("protocol-paths")));
+        for request in [
+            "load splits.json",
+            "new --game Game splits.json",
+            "export splits.json",
+        ] {
+            let response = execute(&timer, &Request::parse(request).unwrap());
+            assert_eq!(
+                response.error.as_deref(),
+                Some("Expected an absolute path: splits.json")
+            );
+        }
+        remove(&timer.lock().unwrap());
+    }
+}
@@ -0,0 +1,396 @@
+use std::error::Error;
+
+use livesplit_core::{
+    analysis::total_playtime::TotalPlaytime, AtomicDateTime, Run, TimeSpan, TimingMethod,
+};
+use serde::Serialize;
+
+use crate::{config::Config, time_format::TimeFormat, wl_split_timer::WlSplitTimer};
+
+/// Summary of the attempt and segment history of a run
+#[derive(Debug, Serialize)]
+pub struct Stats {
+    pub game_name: String,
+    pub category_name: String,
+    pub timing_method: TimingMethod,
+    pub attempts: usize,
+    pub completed: usize,
+    pub completion_rate: f64,
+    pub total_playtime: String,
+    pub segments: Vec<SegmentStats>,
+    pub personal_best_progression: Vec<Progression>,
+    pub sum_of_best_progression: Vec<Progression>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SegmentStats {
+    pub name: String,
+    /// Attempts that reached this segment
+    pub started: usize,
+    /// Attempts that were reset during this segment
+    pub resets: usize,
+    pub reset_rate: f64,
+    pub average: Option<String>,
+    pub median: Option<String>,
+    pub standard_deviation: Option<String>,
+    pub best: Option<String>,
+}
+
+/// An attempt that improved on all attempts before it
+#[derive(Debug, Serialize)]
+pub struct Progression {
+    pub attempt: i32,
+    pub date: Option<String>,
+    pub time: String,
+}
+
+/// Prints the statistics of a run, as JSON or as a plain text report
+pub fn stats(timer: &WlSplitTimer, json: bool, config: &Config) -> Result<(), Box<dyn Error>> {
+    let stats = Stats::new(timer.run(), config.timing_method);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&stats)?);
+    } else {
+        print!("{}", stats.report());
+    }
+    Ok(())
+}
+
+impl Stats {
+    pub fn new(run: &Run, method: TimingMethod) -> Self {
+        let segments = run.segments();
+        let attempts = run.attempt_history();
+
+        let mut started = vec![0; segments.len()];
+        let mut resets = vec![0; segments.len()];
+        let mut completed = 0;
+        for attempt in attempts {
+            // Segments done in an attempt have an entry in their history, even
+            // if they were skipped
+            let reached = segments
+                .iter()
+                .position(|segment| segment.segment_history().get(attempt.index()).is_none())
+                .unwrap_or(segments.len());
+            let time = attempt.time();
+            if time.real_time.is_some() || time.game_time.is_some() {
+                completed += 1;
+            } else if let Some(resets) = resets.get_mut(reached.min(segments.len() - 1)) {
+                *resets += 1;
+            }
+            for started in started.iter_mut().take(reached + 1) {
+                *started += 1;
+            }
+        }
+
+        let segment_stats = segments
+            .iter()
+            .enumerate()
+            .map(|(index, segment)| {
+                let mut times: Vec<f64> = segment
+                    .segment_history()
+                    .iter_actual_runs()
+                    .filter_map(|(id, _)| segment_time(run, index, *id, method))
+                    .map(|time| time.total_milliseconds())
+                    .collect();
+                times.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                SegmentStats {
+                    name: segment.name().to_string(),
+                    started: started[index],
+                    resets: resets[index],
+                    reset_rate: rate(resets[index], started[index]),
+                    average: average(&times).map(format_msecs),
+                    median: median(&times).map(format_msecs),
+                    standard_deviation: standard_deviation(&times).map(format_msecs),
+                    best: segment.best_segment_time()[method].map(format_time),
+                }
+            })
+            .collect();
+
+        Self {
+            game_name: run.game_name().to_string(),
+            category_name: run.category_name().to_string(),
+            timing_method: method,
+            attempts: attempts.len(),
+            completed,
+            completion_rate: rate(completed, attempts.len()),
+            total_playtime: format_time(run.total_playtime()),
+            segments: segment_stats,
+            personal_best_progression: personal_best_progression(run, method),
+            sum_of_best_progression: sum_of_best_progression(run, method),
+        }
+    }
+
+    pub fn report(&self) -> String {
+        let mut report = format!(
+            "{} ({}), {:?}\nAttempts: {}, completed: {} ({:.1}%)\nTotal playtime: {}\n\n",
+            self.game_name,
+            self.category_name,
+            self.timing_method,
+            self.attempts,
+            self.completed,
+            self.completion_rate * 100.0,
+            self.total_playtime
+        );
+        let width = self
+            .segments
+            .iter()
+            .map(|segment| segment.name.chars().count())
+            .max()
+            .unwrap_or(0)
+            .max("Segment".len());
+        report += &format!(
+            "{:<width$}  {:>12}  {:>12}  {:>12}  {:>12}  {:>14}\n",
+            "Segment",
+            "Average",
+            "Median",
+            "Std dev",
+            "Best",
+            "Resets",
+            width = width
+        );
+        let or_dash = |time: &Option<String>| time.clone().unwrap_or_else(|| "-".to_string());
+        for segment in &self.segments {
+            report += &format!(
+                "{:<width$}  {:>12}  {:>12}  {:>12}  {:>12}  {:>14}\n",
+                segment.name,
+                or_dash(&segment.average),
+                or_dash(&segment.median),
+                or_dash(&segment.standard_deviation),
+                or_dash(&segment.best),
+                format!(
+                    "{}/{} ({:.1}%)",
+                    segment.resets,
+                    segment.started,
+                    segment.reset_rate * 100.0
+                ),
+                width = width
+            );
+        }
+        for (title, progression) in [
+            ("Personal best progression", &self.personal_best_progression),
+            ("Sum of best progression", &self.sum_of_best_progression),
+        ]
+        .iter()
+        {
+            report += &format!("\n{}\n", title);
+            if progression.is_empty() {
+                report += "-\n";
+            }
+            for entry in progression.iter() {
+                report += &format!(
+                    "#{:<6} {:<10}  {}\n",
+                    entry.attempt,
+                    entry
+                        .date
+                        .as_deref()
+                        .and_then(|date| date.get(..10))
+                        .unwrap_or("-"),
+                    entry.time
+                );
+            }
+        }
+        report
+    }
+}
+
+fn personal_best_progression(run: &Run, method: TimingMethod) -> Vec<Progression> {
+    let mut progression = Vec::new();
+    let mut best: Option<TimeSpan> = None;
+    for attempt in run.attempt_history() {
+        if let Some(time) = attempt.time()[method] {
+            if best.is_none_or(|best| time < best) {
+                best = Some(time);
+                progression.push(Progression {
+                    attempt: attempt.index(),
+                    date: format_date(attempt.ended().or_else(|| attempt.started())),
+                    time: format_time(time),
+                });
+            }
+        }
+    }
+    progression
+}
+
+/// Sum of the best segments as it was after each attempt, only counting
+/// segment times that were actually achieved in an attempt
+fn sum_of_best_progression(run: &Run, method: TimingMethod) -> Vec<Progression> {
+    let mut progression = Vec::new();
+    let mut best_segments: Vec<Option<TimeSpan>> = vec![None; run.len()];
+    let mut best: Option<TimeSpan> = None;
+    for attempt in run.attempt_history() {
+        for (index, best_segment) in best_segments.iter_mut().enumerate() {
+            if let Some(time) = segment_time(run, index, attempt.index(), method) {
+                if best_segment.is_none_or(|best| time < best) {
+                    *best_segment = Some(time);
+                }
+            }
+        }
+        let sum = best_segments
+            .iter()
+            .try_fold(TimeSpan::zero(), |sum, time| time.map(|time| sum + time));
+        if let Some(sum) = sum {
+            if best.is_none_or(|best| sum < best) {
+                best = Some(sum);
+                progression.push(Progression {
+                    attempt: attempt.index(),
+                    date: format_date(attempt.ended().or_else(|| attempt.started())),
+                    time: format_time(sum),
+                });
+            }
+        }
+    }
+    progression
+}
+
+/// Time of a segment in the attempt with the given id. After a skipped
+/// segment the history holds the combined time of both, which is left out
+/// like in livesplit-core's comparisons.
+fn segment_time(run: &Run, index: usize, id: i32, method: TimingMethod) -> Option<TimeSpan> {
+    let combined = index
+        .checked_sub(1)
+        .and_then(|previous| run.segment(previous).segment_history().get(id))
+        .is_some_and(|time| time[method].is_none());
+    if combined {
+        return None;
+    }
+    run.segment(index).segment_history().get(id)?[method]
+}
+
+fn rate(count: usize, total: usize) -> f64 {
+    if total == 0 {
+        return 0.0;
+    }
+    count as f64 / total as f64
+}
+
+fn average(times: &[f64]) -> Option<f64> {
+    if times.is_empty() {
+        return None;
+    }
+    Some(times.iter().sum::<f64>() / times.len() as f64)
+}
+
+/// Median of times sorted in ascending order
+fn median(times: &[f64]) -> Option<f64> {
+    let middle = times.len() / 2;
+    match times.len() {
+        0 => None,
+        len if len % 2 == 0 => Some((times[middle - 1] + times[middle]) / 2.0),
+        _ => Some(times[middle]),
+    }
+}
+
+fn standard_deviation(times: &[f64]) -> Option<f64> {
+    let average = average(times)?;
+    let variance = times
+        .iter()
+        .map(|time| (time - average).powi(2))
+        .sum::<f64>()
+        / times.len() as f64;
+    Some(variance.sqrt())
+}
+
+fn format_msecs(msecs: f64) -> String {
+    format_time(TimeSpan::from_milliseconds(msecs))
+}
+
+fn format_time(time: TimeSpan) -> String {
+    TimeFormat::for_file().format_time_span(time)
+}
+
+fn format_date(date: Option<AtomicDateTime>) -> Option<String> {
+    date.map(|date| date.time.to_rfc3339())
+}
+
+#[cfg(test)]
+mod tests {
+    use livesplit_core::{Segment, Time};
+
+    use super::*;
+
+    #[test]
+    fn computes_averages() {
+        assert_eq!(average(&[]), None);
+        assert_eq!(average(&[1.0, 2.0, 6.0]), Some(3.0));
+        assert_eq!(median(&[]), None);
+        assert_eq!(median(&[1.0, 2.0, 6.0]), Some(2.0));
+        assert_eq!(median(&[1.0, 2.0, 4.0, 6.0]), Some(3.0));
+    }
+
+    #[test]
+    fn computes_standard_deviation() {
+        assert_eq!(standard_deviation(&[]), None);
+        assert_eq!(standard_deviation(&[3.0]), Some(0.0));
+        let times = [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0];
+        assert_eq!(standard_deviation(&times), Some(2.0));
+    }
+
+    fn time(seconds: f64) -> Time {
+        Time::new().with_real_time(Some(TimeSpan::from_seconds(seconds)))
+    }
+
+    #[test]
+    fn counts_resets_per_segment() {
+        let mut run = Run::new();
+        for name in ["a", "b", "c"] {
+            run.push_segment(Segment::new(name));
+        }
+        // A finished attempt and one reset in the second segment
+        run.add_attempt_with_index(time(6.0), 1, None, None, None);
+        run.add_attempt_with_index(Time::default(), 2, None, None, None);
+        for (index, seconds) in [1.0, 2.0, 3.0].iter().enumerate() {
+            run.segment_mut(index)
+                .segment_history_mut()
+                .insert(1, time(*seconds));
+        }
+        run.segment_mut(0)
+            .segment_history_mut()
+            .insert(2, time(3.0));
+
+        let stats = Stats::new(&run, TimingMethod::RealTime);
+        assert_eq!(stats.attempts, 2);
+        assert_eq!(stats.completed, 1);
+        assert_eq!(stats.completion_rate, 0.5);
+        let started: Vec<_> = stats.segments.iter().map(|s| s.started).collect();
+        assert_eq!(started, [2, 2, 1]);
+        let resets: Vec<_> = stats.segments.iter().map(|s| s.resets).collect();
+        assert_eq!(resets, [0, 1, 0]);
+        assert_eq!(stats.segments[0].median.as_deref(), Some("00:00:02.000"));
+        assert_eq!(stats.personal_best_progression.len(), 1);
+    }
+
+    #[test]
+    fn leaves_out_times_after_skipped_segments() {
+        let mut run = Run::new();
+        for name in ["a", "b", "c"] {
+            run.push_segment(Segment::new(name));
+        }
+        run.add_attempt_with_index(time(6.0), 1, None, None, None);
+        run.add_attempt_with_index(time(3.0), 2, None, None, None);
+        // The second attempt skipped a, so b has the time of a and b
+        for (index, seconds) in [1.0, 2.0, 3.0].iter().enumerate() {
+            run.segment_mut(index)
+                .segment_history_mut()
+                .insert(1, time(*seconds));
+        }
+        run.segment_mut(0)
+            .segment_history_mut()
+            .insert(2, Time::default());
+        run.segment_mut(1)
+            .segment_history_mut()
+            .insert(2, time(2.5));
+        run.segment_mut(2)
+            .segment_history_mut()
+            .insert(2, time(0.5));
+
+        let stats = Stats::new(&run, TimingMethod::RealTime);
+        assert_eq!(stats.segments[1].average.as_deref(), Some("00:00:02.000"));
+        assert_eq!(stats.segments[1].median.as_deref(), Some("00:00:02.000"));
+        assert_eq!(stats.segments[2].average.as_deref(), Some("00:00:01.750"));
+        let sum_of_best: Vec<_> = stats
+            .sum_of_best_progression
+            .iter()
+            .map(|entry| entry.time.as_str())
+            .collect();
+        assert_eq!(sum_of_best, ["00:00:06.000", "00:00:03.500"]);
+    }
+}
@@ -1,3 +1,5 @@
+use livesplit_core::TimeSpan;
+
 const MSEC_HOUR: u128 = 3600000;
 const MSEC_MINUTE: u128 = 60000;
 const MSEC_SECOND: u128 = 1000;
@@ -26,6 +28,11 @@ impl TimeFormat {
         }
     }
 
+    pub fn format_time_span(&self, time: TimeSpan) -> String {
+        let msecs = time.total_milliseconds();
+        self.format_time(msecs.abs() as u128, msecs < 0.0)
+    }
+
     pub fn format_time(&self, time: u128, negative: bool) -> String {
         let prefix = if negative {
             "-"
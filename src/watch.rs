@@ -0,0 +1,104 @@
+use std::{
+    error::Error,
+    ffi::{OsStr, OsString},
+    fs,
+    io::ErrorKind,
+    mem,
+    path::Path,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use inotify::{Inotify, WatchDescriptor, WatchMask};
+
+use crate::{config, wl_split_timer::WlSplitTimer};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// A file watched through its directory
+struct Watch {
+    descriptor: WatchDescriptor,
+    name: OsString,
+}
+
+/// Reloads the splits file and the config when another program changes them.
+/// The directories are watched rather than the files, since editors (and
+/// wlsplit itself) replace files by renaming a new one over them.
+pub fn spawn(timer: Arc<Mutex<WlSplitTimer>>) -> Result<(), Box<dyn Error>> {
+    let mut inotify = Inotify::init()?;
+    let config = config::path().ok_or("Unable to find the config directory")?;
+    let config = watch(&mut inotify, &config)?;
+    let mut file = timer.lock().unwrap().file().to_string();
+    let mut splits = watch(&mut inotify, Path::new(&file))?;
+
+    thread::spawn(move || {
+        let mut buffer = [0; 4096];
+        loop {
+            // Another splits file may have been loaded in the meantime
+            let current = timer.lock().unwrap().file().to_string();
+            if current != file {
+                match watch(&mut inotify, Path::new(&current)) {
+                    Ok(watch) => {
+                        // A directory has a single watch, which may still be
+                        // needed for the new file or the config
+                        let old = mem::replace(&mut splits, watch).descriptor;
+                        if old != splits.descriptor && old != config.descriptor {
+                            inotify.rm_watch(old).ok();
+                        }
+                    }
+                    Err(e) => eprintln!("Unable to watch {}: {}", current, e),
+                }
+                file = current;
+            }
+
+            let events = match inotify.read_events(&mut buffer) {
+                Ok(events) => events,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                    thread::sleep(POLL_INTERVAL);
+                    continue;
+                }
+                Err(e) => {
+                    eprintln!("Stopped watching files: {}", e);
+                    return;
+                }
+            };
+            let (mut splits_changed, mut config_changed) = (false, false);
+            for event in events {
+                splits_changed |= splits.matches(&event.wd, event.name);
+                config_changed |= config.matches(&event.wd, event.name);
+            }
+            let mut timer = timer.lock().unwrap();
+            // Changes to the splits during an attempt are overwritten when it ends
+            if splits_changed {
+                if let Err(e) = timer.reload_file_if_changed() {
+                    eprintln!("{}", e);
+                }
+            }
+            if config_changed {
+                timer.reload_config = true;
+            }
+        }
+    });
+    Ok(())
+}
+
+fn watch(inotify: &mut Inotify, path: &Path) -> Result<Watch, Box<dyn Error>> {
+    let path = fs::canonicalize(path)?;
+    let directory = path.parent().ok_or("Not a file")?;
+    let name = path.file_name().ok_or("Not a file")?;
+    let descriptor = inotify.add_watch(
+        directory,
+        WatchMask::CLOSE_WRITE | WatchMask::MOVED_TO | WatchMask::CREATE,
+    )?;
+    Ok(Watch {
+        descriptor,
+        name: name.to_owned(),
+    })
+}
+
+impl Watch {
+    fn matches(&self, descriptor: &WatchDescriptor, name: Option<&OsStr>) -> bool {
+        *descriptor == self.descriptor && name == Some(self.name.as_os_str())
+    }
+}
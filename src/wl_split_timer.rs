@@ -1,8 +1,32 @@
-use std::error::Error;
+use std::{
+    error::Error,
+    fs, mem,
+    path::Path,
+    sync::mpsc::{channel, Receiver, Sender},
+    time::SystemTime,
+};
 
-use crate::file::{self, Run as RunFile};
+use crate::{
+    event::Event,
+    file::{self, FileFormat, Run as RunFile},
+    journal::{self, Interrupted, Journal, Recovery},
+    time_format::TimeFormat,
+};
 use chrono::{DateTime, Utc};
-use livesplit_core::{AtomicDateTime, Run, Segment, Time, TimeSpan, Timer, TimerPhase};
+use livesplit_core::{
+    analysis,
+    comparison::{average_segments, best_segments, latest_run, median_segments},
+    AtomicDateTime, Run, Segment, Time, TimeSpan, Timer, TimerPhase, TimingMethod,
+};
+
+const COMPARISON_GENERATORS: [&str; 4] = [
+    best_segments::NAME,
+    average_segments::NAME,
+    median_segments::NAME,
+    latest_run::NAME,
+];
+
+pub const DEFAULT_BACKUPS: usize = 5;
 
 const MSEC_HOUR: u128 = 3600000;
 const MSEC_MINUTE: u128 = 60000;
@@ -16,11 +40,24 @@ pub struct RunMetadata<'a> {
 pub struct WlSplitTimer {
     timer: Timer,
     file: String,
+    format: FileFormat,
+    redo_stack: Vec<Timer>,
+    subscribers: Vec<Sender<Event>>,
+    journal: Journal,
+    /// Attempt resumed from the journal and the run offset it replaced
+    resumed: Option<(Interrupted, TimeSpan)>,
+    backups: usize,
+    /// Modification time of the file after wlsplit last wrote or read it
+    written: Option<SystemTime>,
+    /// Set when the run changed since it was last written
+    unsaved: bool,
     pub exit: bool,
+    /// Set by `reload`, the config is re-read by the main loop
+    pub reload_config: bool,
 }
 
 impl WlSplitTimer {
-    pub fn new(file: String, metadata: RunMetadata) -> Self {
+    pub fn new(file: String, metadata: RunMetadata) -> Result<Self, Box<dyn Error>> {
         let mut run = Run::new();
 
         let mut generated = RunFile::default();
@@ -33,26 +70,100 @@ impl WlSplitTimer {
         if let Some(splits) = metadata.splits {
             generated = generated.with_splits(splits);
         }
-        file_to_run(generated, &mut run);
-        write_file(&file, &run).expect("Could not write file");
-        let timer = Timer::new(run).unwrap();
+        file_to_run(generated, &mut run)?;
+        let format = FileFormat::from_path(&file);
+        write_file(&file, &run, format)?;
+        set_comparison_generators(&mut run);
+        let timer = Timer::new(run).map_err(|_| "At least one segment expected")?;
+        let mut journal = Journal::new(&file);
+        journal.clear();
+        let written = modified(&file);
 
-        Self {
+        Ok(Self {
             timer,
             file,
+            format,
+            redo_stack: Vec::new(),
+            subscribers: Vec::new(),
+            journal,
+            resumed: None,
+            backups: DEFAULT_BACKUPS,
+            written,
+            unsaved: false,
             exit: false,
-        }
+            reload_config: false,
+        })
     }
 
-    pub fn from_file(file: String) -> Self {
-        let mut run = Run::new();
-        read_file(&file, &mut run).expect("Unable to parse file");
-        let timer = Timer::new(run).expect("At least one segment expected");
+    pub fn from_file(file: String) -> Result<Self, Box<dyn Error>> {
+        let format = FileFormat::detect(&file);
+        let mut run = read_file(&file, format)?;
+        set_comparison_generators(&mut run);
+        let timer = Timer::new(run).map_err(|_| "At least one segment expected")?;
+        let journal = Journal::new(&file);
+        let written = modified(&file);
 
-        Self {
+        Ok(Self {
             timer,
             file,
+            format,
+            redo_stack: Vec::new(),
+            subscribers: Vec::new(),
+            journal,
+            resumed: None,
+            backups: DEFAULT_BACKUPS,
+            written,
+            unsaved: false,
             exit: false,
+            reload_config: false,
+        })
+    }
+
+    /// Picks up an attempt that was still running when wlsplit last exited
+    pub fn recover(&mut self, recovery: Recovery) {
+        let interrupted = match self.journal.read() {
+            Some(interrupted) => interrupted,
+            None => {
+                self.journal.clear();
+                return;
+            }
+        };
+        let mut run = self.timer.run().clone();
+        match recovery.resolve(&interrupted) {
+            Recovery::Resume if !interrupted.is_finished(&run) => {
+                // The timer can't be given earlier split times, so it starts
+                // at the elapsed time with the recovered segments skipped and
+                // their times are merged back in on reset.
+                let offset = run.offset();
+                let elapsed = interrupted.elapsed();
+                run.set_offset(elapsed.real_time.unwrap_or_default());
+                self.timer.set_run(run).ok();
+                self.timer.start();
+                self.timer.initialize_game_time();
+                if let Some(game_time) = elapsed.game_time {
+                    self.timer.set_game_time(game_time);
+                }
+                for _ in &interrupted.splits {
+                    self.timer.skip_split();
+                }
+                if interrupted.game_time_paused {
+                    self.timer.pause_game_time();
+                }
+                if interrupted.paused {
+                    self.timer.pause();
+                }
+                self.resumed = Some((interrupted, offset));
+            }
+            Recovery::Discard => self.journal.clear(),
+            _ => {
+                run.start_next_run();
+                interrupted.commit(&mut run, self.timing_method());
+                self.timer.set_run(run).ok();
+                self.unsaved = true;
+                if self.write_file().is_ok() {
+                    self.journal.clear();
+                }
+            }
         }
     }
 
@@ -72,45 +183,448 @@ impl WlSplitTimer {
         self.timer.run().category_name()
     }
 
+    pub fn subscribe(&mut self) -> Receiver<Event> {
+        let (sender, receiver) = channel();
+        self.subscribers.push(sender);
+        receiver
+    }
+
+    fn emit(&mut self, event: Event) {
+        match event {
+            Event::Reset | Event::Gold { .. } | Event::PersonalBest { .. } => {}
+            _ => {
+                let time = match event {
+                    Event::Split { index, .. } => self.segment_split_time(index),
+                    _ => self.timer.current_time(),
+                };
+                if let Err(e) = self.journal.record(&event, time) {
+                    eprintln!("Could not write journal: {}", e);
+                }
+            }
+        }
+        self.subscribers
+            .retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+
     pub fn start(&mut self) {
+        self.redo_stack.clear();
+        let phase = self.timer.current_phase();
         self.timer.start();
+        self.timer.initialize_game_time();
+        if phase == TimerPhase::NotRunning {
+            self.emit(Event::Started);
+        }
     }
 
     pub fn pause(&mut self) {
+        self.redo_stack.clear();
+        let phase = self.timer.current_phase();
         self.timer.toggle_pause_or_start();
+        if !self.timer.is_game_time_initialized() {
+            self.timer.initialize_game_time();
+        }
+        match (phase, self.timer.current_phase()) {
+            (TimerPhase::NotRunning, TimerPhase::Running) => self.emit(Event::Started),
+            (TimerPhase::Running, TimerPhase::Paused) => self.emit(Event::Paused),
+            (TimerPhase::Paused, TimerPhase::Running) => self.emit(Event::Resumed),
+            _ => {}
+        }
+    }
+
+    pub fn pause_game_time(&mut self) {
+        let paused = self.timer.is_game_time_paused();
+        self.timer.pause_game_time();
+        if !paused && self.timer.current_phase() != TimerPhase::NotRunning {
+            self.emit(Event::GameTimePaused);
+        }
+    }
+
+    pub fn resume_game_time(&mut self) {
+        let paused = self.timer.is_game_time_paused();
+        self.timer.resume_game_time();
+        if paused && self.timer.current_phase() != TimerPhase::NotRunning {
+            self.emit(Event::GameTimeResumed);
+        }
+    }
+
+    pub fn set_game_time(&mut self, time: TimeSpan) {
+        if self.timer.current_phase() != TimerPhase::NotRunning {
+            self.timer.set_game_time(time);
+            self.emit(Event::GameTimeSet);
+        }
+    }
+
+    pub fn set_loading_times(&mut self, time: TimeSpan) {
+        if self.timer.current_phase() != TimerPhase::NotRunning {
+            self.timer.set_loading_times(time);
+            self.emit(Event::GameTimeSet);
+        }
+    }
+
+    pub fn timing_method(&self) -> TimingMethod {
+        self.timer.current_timing_method()
     }
 
-    pub fn split(&mut self) {
+    pub fn secondary_timing_method(&self) -> TimingMethod {
+        match self.timing_method() {
+            TimingMethod::RealTime => TimingMethod::GameTime,
+            TimingMethod::GameTime => TimingMethod::RealTime,
+        }
+    }
+
+    pub fn set_timing_method(&mut self, method: TimingMethod) {
+        self.timer.set_current_timing_method(method);
+    }
+
+    pub fn toggle_timing_method(&mut self) {
+        self.timer.toggle_timing_method();
+    }
+
+    /// Ending the run writes the file, see `reset`
+    pub fn split(&mut self) -> Result<(), String> {
+        self.redo_stack.clear();
+        let index = self.current_segment_index();
         self.timer.split();
+        if let Some(index) = index.filter(|index| Some(*index) != self.current_segment_index()) {
+            self.emit_split(index);
+        }
         let end_of_run = self.timer.current_phase() == TimerPhase::Ended;
 
         if end_of_run {
-            self.reset(true);
-            self.write_file().ok();
+            let method = self.timing_method();
+            let last = self.segments().last().unwrap();
+            let time = last.split_time()[method];
+            let personal_best = match (time, last.personal_best_split_time()[method]) {
+                (Some(time), Some(pb)) => time < pb,
+                (Some(_), None) => true,
+                _ => false,
+            };
+            if personal_best {
+                self.emit(Event::PersonalBest {
+                    time: time.map(|time| TimeFormat::for_file().format_time_span(time)),
+                });
+            }
+            return self.reset(true);
+        }
+        Ok(())
+    }
+
+    fn emit_split(&mut self, index: usize) {
+        let method = self.timing_method();
+        let segment = &self.segments()[index];
+        let name = segment.name().to_string();
+        let time = segment.split_time()[method];
+        let delta = match (time, self.comparison_split_time(index)) {
+            (Some(time), Some(pb)) => Some(TimeFormat::for_diff().format_time_span(time - pb)),
+            _ => None,
+        };
+        let best_segment_time = segment.best_segment_time()[method];
+        let segment_time = self.get_segment_time(index);
+        self.emit(Event::Split {
+            index,
+            segment: name.clone(),
+            time: time.map(|time| TimeFormat::for_file().format_time_span(time)),
+            delta,
+        });
+
+        if let (Some(segment_time), Some(best)) = (segment_time, best_segment_time) {
+            if (segment_time as f64) < best.total_milliseconds() {
+                self.emit(Event::Gold {
+                    index,
+                    segment: name,
+                    segment_time: Some(
+                        TimeFormat::for_file().format_time(segment_time as u128, false),
+                    ),
+                });
+            }
         }
     }
 
     pub fn skip(&mut self) {
+        self.redo_stack.clear();
+        let index = self.current_segment_index();
         self.timer.skip_split();
+        if let Some(index) = index.filter(|index| Some(*index) != self.current_segment_index()) {
+            let segment = self.segments()[index].name().to_string();
+            self.emit(Event::Skipped { index, segment });
+        }
+    }
+
+    pub fn undo(&mut self) {
+        let previous = self.timer.clone();
+        self.timer.undo_split();
+        if self.timer.current_split_index() != previous.current_split_index() {
+            self.redo_stack.push(previous);
+            if let Some(index) = self.current_segment_index() {
+                let segment = self.segments()[index].name().to_string();
+                self.emit(Event::Undo { index, segment });
+            }
+        }
+    }
+
+    pub fn undo_skip(&mut self) {
+        let skipped = self
+            .current_segment_index()
+            .filter(|index| *index > 0)
+            .is_some_and(|index| {
+                self.segment_split_time(index - 1)[self.timing_method()].is_none()
+            });
+        if skipped {
+            self.undo();
+        }
+    }
+
+    /// livesplit-core has no way to restore a removed split time, so the timer
+    /// state from before the undo is restored instead. Splits, resets and
+    /// pauses clear the redo stack, so that none of them is reverted by this.
+    /// Game time, timing method and comparison keep changing in between, e.g.
+    /// through an autosplitter, and are carried over to the restored timer.
+    pub fn redo(&mut self) {
+        if let Some(mut timer) = self.redo_stack.pop() {
+            if self.timer.is_game_time_paused() {
+                timer.pause_game_time();
+            } else {
+                timer.resume_game_time();
+            }
+            if let Some(game_time) = self.timer.current_time().game_time {
+                timer.set_game_time(game_time);
+            }
+            timer.set_current_timing_method(self.timing_method());
+            timer.set_current_comparison(self.comparison()).ok();
+            self.timer = timer;
+            self.emit(Event::Redo);
+        }
+    }
+
+    /// The journal is kept if the attempt can't be written to the file, so
+    /// that it is recovered on the next start. Without an attempt nothing is
+    /// written.
+    pub fn reset(&mut self, update_splits: bool) -> Result<(), String> {
+        self.redo_stack.clear();
+        let phase = self.timer.current_phase();
+        match self.resumed.take() {
+            Some((attempt, offset)) => self.reset_resumed(attempt, offset, update_splits),
+            None => self.timer.reset(update_splits),
+        }
+        let result = if update_splits && phase != TimerPhase::NotRunning {
+            self.unsaved = true;
+            self.write_file()
+                .map_err(|e| format!("Unable to write file: {}", e))
+        } else {
+            Ok(())
+        };
+        if result.is_ok() {
+            self.journal.clear();
+        }
+        if phase != TimerPhase::NotRunning {
+            self.emit(Event::Reset);
+        }
+        result
     }
 
-    pub fn reset(&mut self, update_splits: bool) {
-        self.timer.reset(update_splits);
+    fn reset_resumed(&mut self, mut attempt: Interrupted, offset: TimeSpan, update_splits: bool) {
+        let index = self.current_segment_index().unwrap_or(0);
+        let mut run = self.timer.run().clone();
+        attempt.splits = run
+            .segments()
+            .iter()
+            .take(index)
+            .enumerate()
+            .map(|(i, segment)| match segment.split_time() {
+                time if time.real_time.is_some() => time,
+                _ => attempt.splits.get(i).copied().unwrap_or_default(),
+            })
+            .collect();
+        attempt.time = self.timer.current_time();
+        attempt.timestamp = Utc::now();
+
+        self.timer.reset(false);
+        run.set_offset(offset);
         if update_splits {
-            self.write_file().ok();
+            attempt.commit(&mut run, self.timing_method());
+        } else {
+            journal::clear_splits(&mut run);
         }
+        self.timer.set_run(run).ok();
     }
 
     pub fn quit(&mut self) {
         self.exit = true;
     }
 
-    pub fn write_file(&self) -> Result<(), Box<dyn Error>> {
-        write_file(&self.file, &self.timer.run())
+    pub fn set_backups(&mut self, backups: usize) {
+        self.backups = backups;
+    }
+
+    pub fn write_file(&mut self) -> Result<(), Box<dyn Error>> {
+        self.write_file_sparing(None)
+    }
+
+    /// Writes the file, the backup named `spare` is not removed with the
+    /// oldest ones
+    fn write_file_sparing(&mut self, spare: Option<&str>) -> Result<(), Box<dyn Error>> {
+        if let Err(e) = file::backup(&self.file, self.backups, spare) {
+            eprintln!("Could not back up {}: {}", self.file, e);
+        }
+        write_file(&self.file, self.timer.run(), self.format)?;
+        self.written = modified(&self.file);
+        self.unsaved = false;
+        Ok(())
+    }
+
+    /// Re-reads the splits file and has the main loop re-read the config
+    pub fn reload(&mut self) -> Result<(), String> {
+        self.reload_file()?;
+        self.reload_config = true;
+        Ok(())
+    }
+
+    /// Re-reads the splits file if it was changed by another program since
+    /// wlsplit last wrote it
+    pub fn reload_file_if_changed(&mut self) -> Result<(), String> {
+        if modified(&self.file) == self.written {
+            return Ok(());
+        }
+        self.reload_file()
+    }
+
+    fn reload_file(&mut self) -> Result<(), String> {
+        if self.timer.current_phase() != TimerPhase::NotRunning {
+            return Err("The splits can't be reloaded during an attempt".to_string());
+        }
+        let mut run = read_file(&self.file, FileFormat::detect(&self.file))
+            .map_err(|e| format!("Unable to read {}: {}", self.file, e))?;
+        set_comparison_generators(&mut run);
+        self.timer
+            .set_run(run)
+            .map_err(|_| "At least one segment expected".to_string())?;
+        self.redo_stack.clear();
+        self.written = modified(&self.file);
+        self.unsaved = false;
+        Ok(())
+    }
+
+    /// Switches to another splits file, see `switch_to`. An attempt
+    /// interrupted in that file is committed to its history.
+    pub fn open(&mut self, file: String) -> Result<(), String> {
+        self.prepare_switch()?;
+        let mut timer = WlSplitTimer::from_file(file.clone())
+            .map_err(|e| format!("Unable to load {}: {}", file, e))?;
+        timer.recover(Recovery::Commit);
+        self.switch_to(timer);
+        Ok(())
+    }
+
+    /// Switches to a newly generated splits file, see `switch_to`
+    pub fn create(&mut self, file: String, metadata: RunMetadata) -> Result<(), String> {
+        if Path::new(&file).exists() {
+            return Err(format!("{} already exists", file));
+        }
+        self.prepare_switch()?;
+        let timer = WlSplitTimer::new(file.clone(), metadata)
+            .map_err(|e| format!("Unable to create {}: {}", file, e))?;
+        self.switch_to(timer);
+        Ok(())
+    }
+
+    /// Saves unsaved changes of the current file before another one is read
+    /// or created, so that nothing is left behind if it can't be saved. A file
+    /// without changes is left as it is, it may have been edited elsewhere.
+    fn prepare_switch(&mut self) -> Result<(), String> {
+        if self.timer.current_phase() != TimerPhase::NotRunning {
+            return Err("The splits can't be switched during an attempt".to_string());
+        }
+        if self.unsaved {
+            self.write_file()
+                .map_err(|e| format!("Unable to write file: {}", e))?;
+        }
+        Ok(())
+    }
+
+    /// Replaces this timer with the given one, keeping the timing method,
+    /// comparison, backups and subscribers
+    fn switch_to(&mut self, mut timer: WlSplitTimer) {
+        timer.set_timing_method(self.timing_method());
+        timer.set_backups(self.backups);
+        // Custom comparisons only exist in the file that defines them
+        timer.set_comparison(self.comparison()).ok();
+        timer.subscribers = mem::take(&mut self.subscribers);
+        *self = timer;
+    }
+
+    /// Whether a config reload was requested and no attempt is running
+    pub fn config_reload_pending(&self) -> bool {
+        self.reload_config && self.timer.current_phase() == TimerPhase::NotRunning
+    }
+
+    pub fn file(&self) -> &str {
+        &self.file
+    }
+
+    pub fn list_backups(&self) -> Vec<String> {
+        file::list_backups(&self.file)
+    }
+
+    /// Replaces the run with a backup, given by its name or its position in
+    /// `list_backups` starting at 1. The current file is backed up first, the
+    /// restored backup is kept even if it is the oldest one.
+    pub fn restore_backup(&mut self, backup: &str) -> Result<(), String> {
+        if self.timer.current_phase() != TimerPhase::NotRunning {
+            return Err("Backups can't be restored during an attempt".to_string());
+        }
+        let backups = self.list_backups();
+        let name = match backup.parse::<usize>() {
+            Ok(position) => position.checked_sub(1).and_then(|i| backups.get(i)),
+            Err(_) => backups.iter().find(|name| name.as_str() == backup),
+        }
+        .ok_or_else(|| format!("Unknown backup: {}", backup))?;
+
+        let path = file::backup_path(&self.file, name);
+        let path = path.to_string_lossy();
+        let run = read_file(&path, FileFormat::detect(&path))
+            .map_err(|e| format!("Unable to read backup {}: {}", name, e))?;
+        self.set_run(run)?;
+        self.write_file_sparing(Some(name))
+            .map_err(|e| format!("Unable to write file: {}", e))
+    }
+
+    /// Replaces the run, e.g. with an edited one, and writes it to the file
+    pub fn save_run(&mut self, run: Run) -> Result<(), String> {
+        self.set_run(run)?;
+        self.write_file()
+            .map_err(|e| format!("Unable to write file: {}", e))
+    }
+
+    fn set_run(&mut self, mut run: Run) -> Result<(), String> {
+        set_comparison_generators(&mut run);
+        self.timer
+            .set_run(run)
+            .map_err(|_| "At least one segment expected".to_string())?;
+        self.unsaved = true;
+        Ok(())
+    }
+
+    /// Writes the run to another file, as LiveSplit splits if the path ends in .lss
+    pub fn export(&self, file: &str) -> Result<(), Box<dyn Error>> {
+        write_file(file, self.timer.run(), FileFormat::from_path(file))
+    }
+
+    /// Writes the attempt history to a CSV file, see `file::write_csv`
+    pub fn export_csv(
+        &self,
+        file: &str,
+        method: TimingMethod,
+        complete_only: bool,
+    ) -> Result<(), Box<dyn Error>> {
+        file::write_csv(file, self.timer.run(), method, complete_only)
     }
 
     pub fn time(&self) -> Option<TimeSpan> {
-        self.timer.current_time().real_time
+        self.time_with_method(self.timing_method())
+    }
+
+    pub fn time_with_method(&self, method: TimingMethod) -> Option<TimeSpan> {
+        self.timer.current_time()[method]
     }
 
     pub fn segments(&self) -> &[Segment] {
@@ -136,7 +650,7 @@ impl WlSplitTimer {
     pub fn sum_of_best_segments(&self) -> usize {
         let mut sum: usize = 0;
         for segment in self.timer.run().segments() {
-            if let Some(time) = segment.best_segment_time().real_time {
+            if let Some(time) = segment.best_segment_time()[self.timing_method()] {
                 sum += time.total_milliseconds() as usize;
             }
         }
@@ -150,18 +664,12 @@ impl WlSplitTimer {
             return self.sum_of_best_segments();
         }
 
-        let mut time: usize = self
-            .run()
-            .segment(index - 1)
-            .split_time()
-            .real_time
+        let mut time: usize = self.run().segment(index - 1).split_time()[self.timing_method()]
             .unwrap_or_default()
             .total_milliseconds() as usize;
 
         for segment in self.run().segments().iter().skip(index) {
-            let segment = segment
-                .best_segment_time()
-                .real_time
+            let segment = segment.best_segment_time()[self.timing_method()]
                 .unwrap_or_default()
                 .total_milliseconds() as usize;
             time += segment;
@@ -173,12 +681,12 @@ impl WlSplitTimer {
     pub fn parse_time_string(time: String) -> Result<u128, Box<dyn Error>> {
         let split: Vec<&str> = time.split(':').collect();
         let mut time: u128 = 0;
-        time += MSEC_HOUR * split.get(0).ok_or("")?.parse::<u128>()?;
+        time += MSEC_HOUR * split.first().ok_or("")?.parse::<u128>()?;
         time += MSEC_MINUTE * split.get(1).ok_or("")?.parse::<u128>()?;
 
         let split: Vec<&str> = split.get(2).ok_or("")?.split('.').collect();
 
-        time += MSEC_SECOND * split.get(0).ok_or("")?.parse::<u128>()?;
+        time += MSEC_SECOND * split.first().ok_or("")?.parse::<u128>()?;
         time += split
             .get(1)
             .ok_or("")?
@@ -190,26 +698,41 @@ impl WlSplitTimer {
         Ok(time)
     }
 
-    pub fn string_to_time(string: String) -> Time {
-        let time = WlSplitTimer::parse_time_string(string)
+    pub fn string_to_time_span(string: String) -> Result<TimeSpan, String> {
+        WlSplitTimer::parse_time_string(string.clone())
             .map(|time| TimeSpan::from_milliseconds(time as f64))
-            .expect("Unable to parse time");
+            .map_err(|_| format!("invalid time \"{}\"", string))
+    }
 
-        Time::new().with_real_time(Some(time))
+    pub fn string_to_time(
+        real_time: Option<String>,
+        game_time: Option<String>,
+    ) -> Result<Time, String> {
+        Ok(Time::new()
+            .with_real_time(
+                real_time
+                    .map(WlSplitTimer::string_to_time_span)
+                    .transpose()?,
+            )
+            .with_game_time(
+                game_time
+                    .map(WlSplitTimer::string_to_time_span)
+                    .transpose()?,
+            ))
     }
 
     pub fn get_segment_time(&self, index: usize) -> Option<usize> {
         let current_time = self
             .segments()
             .get(index)
-            .and_then(|segment| segment.split_time().real_time);
+            .and_then(|segment| segment.split_time()[self.timing_method()]);
         if index == 0 {
             return current_time.map(|time| time.to_duration().num_milliseconds() as usize);
         }
         let time = self
             .segments()
             .get(index - 1)
-            .and_then(|segment| segment.split_time().real_time);
+            .and_then(|segment| segment.split_time()[self.timing_method()]);
         if let (Some(current_time), Some(time)) = (current_time, time) {
             Some(
                 (current_time.to_duration().num_milliseconds()
@@ -220,39 +743,74 @@ impl WlSplitTimer {
         }
     }
 
-    pub fn get_personal_best_index(&self) -> Option<i32> {
-        let history = self.run().attempt_history().to_vec();
-        history
-            .iter()
-            .min_by(|a, b| a.time().real_time.cmp(&b.time().real_time))
-            .map(|attempt| attempt.index())
+    pub fn comparison(&self) -> &str {
+        self.timer.current_comparison()
     }
 
-    pub fn get_personal_best_segment_time(&self, index: usize) -> Option<Time> {
-        self.get_personal_best_index().and_then(|pb_index| {
-            self.run()
-                .segments()
-                .get(index)
-                .and_then(|segment| segment.segment_history().get(pb_index))
-        })
+    pub fn set_comparison(&mut self, comparison: &str) -> Result<(), String> {
+        self.timer
+            .set_current_comparison(comparison)
+            .map_err(|_| format!("Unknown comparison: {}", comparison))
+    }
+
+    pub fn next_comparison(&mut self) {
+        self.timer.switch_to_next_comparison();
+    }
+
+    pub fn previous_comparison(&mut self) {
+        self.timer.switch_to_previous_comparison();
+    }
+
+    /// Difference of the last split that has a time to the given comparison
+    pub fn last_delta(&self, comparison: &str) -> Option<TimeSpan> {
+        let index = self.current_segment_index()?;
+        analysis::last_delta(self.run(), index, comparison, self.timing_method())
+    }
+
+    pub fn comparison_split_time(&self, index: usize) -> Option<TimeSpan> {
+        self.segments()
+            .get(index)
+            .and_then(|segment| segment.comparison(self.comparison())[self.timing_method()])
+    }
+
+    pub fn comparison_segment_time(&self, index: usize) -> Option<TimeSpan> {
+        let split_time = self.comparison_split_time(index)?;
+        if index == 0 {
+            return Some(split_time);
+        }
+        Some(split_time - self.comparison_split_time(index - 1)?)
     }
 }
 
-fn read_file(file: &str, run: &mut Run) -> Result<(), Box<dyn Error>> {
-    file::read_json::<RunFile>(file).map(|json| file_to_run(json, run))
+fn set_comparison_generators(run: &mut Run) {
+    run.comparison_generators_mut()
+        .retain(|generator| COMPARISON_GENERATORS.contains(&generator.name()));
 }
 
-fn file_to_run(file: RunFile, run: &mut Run) {
+fn read_file(file: &str, format: FileFormat) -> Result<Run, Box<dyn Error>> {
+    match format {
+        FileFormat::Json => {
+            let mut run = Run::new();
+            file_to_run(file::read_run(file)?, &mut run)?;
+            Ok(run)
+        }
+        FileFormat::Lss => file::read_lss(file),
+    }
+}
+
+/// Errors name the field that failed, in the same path notation as file::read_run
+fn file_to_run(file: RunFile, run: &mut Run) -> Result<(), String> {
     run.set_game_name(file.game_name);
     run.set_category_name(file.category_name);
     run.set_attempt_count(file.attempt_count as u32);
+    for comparison in file.custom_comparisons {
+        run.add_custom_comparison(comparison).ok();
+    }
 
-    for attempt in file.attempt_history {
-        let time = match attempt.time {
-            Some(t) => t,
-            _ => continue,
-        };
-        let time = WlSplitTimer::string_to_time(time);
+    for (i, attempt) in file.attempt_history.into_iter().enumerate() {
+        let context = |e| format!("attempt_history[{}]: {}", i, e);
+        let time =
+            WlSplitTimer::string_to_time(attempt.time, attempt.game_time).map_err(context)?;
         let started = attempt.started.and_then(|t| {
             DateTime::parse_from_rfc3339(&t)
                 .map(|t| AtomicDateTime::new(t.with_timezone(&Utc), false))
@@ -263,40 +821,242 @@ fn file_to_run(file: RunFile, run: &mut Run) {
                 .map(|t| AtomicDateTime::new(t.with_timezone(&Utc), false))
                 .ok()
         });
-        let pause_time = attempt.pause_time.and_then(|time| {
-            WlSplitTimer::parse_time_string(time)
-                .map(|ms| TimeSpan::from_milliseconds(ms as f64))
-                .ok()
-        });
+        let pause_time = attempt
+            .pause_time
+            .map(WlSplitTimer::string_to_time_span)
+            .transpose()
+            .map_err(context)?;
         run.add_attempt_with_index(time, attempt.id, started, ended, pause_time);
     }
 
-    for segment in file.segments {
-        let best_segment_time = segment
-            .best_segment_time
-            .map_or(Time::new(), WlSplitTimer::string_to_time);
-        let personal_best_split_time = segment
-            .personal_best_split_time
-            .map_or(Time::new(), WlSplitTimer::string_to_time);
+    for (i, segment) in file.segments.into_iter().enumerate() {
+        let context = |e| format!("segments[{}]: {}", i, e);
+        let best_segment_time =
+            WlSplitTimer::string_to_time(segment.best_segment_time, segment.best_segment_game_time)
+                .map_err(context)?;
+        let personal_best_split_time = WlSplitTimer::string_to_time(
+            segment.personal_best_split_time,
+            segment.personal_best_split_game_time,
+        )
+        .map_err(context)?;
 
         let mut segment_new = Segment::new(segment.name);
         segment_new.set_best_segment_time(best_segment_time);
         segment_new.set_personal_best_split_time(personal_best_split_time);
+        for comparison in segment.comparisons {
+            *segment_new.comparison_mut(&comparison.name) =
+                WlSplitTimer::string_to_time(comparison.time, comparison.game_time)
+                    .map_err(context)?;
+        }
 
         for split in segment.segment_history {
             if let Some(id) = split.id {
                 segment_new.segment_history_mut().insert(
                     id,
-                    split.time.map_or(Time::new(), WlSplitTimer::string_to_time),
+                    WlSplitTimer::string_to_time(split.time, split.game_time).map_err(context)?,
                 )
             }
         }
 
         run.push_segment(segment_new);
     }
+    Ok(())
+}
+
+fn write_file(file: &str, run: &Run, format: FileFormat) -> Result<(), Box<dyn Error>> {
+    match format {
+        FileFormat::Json => file::write_json(file, RunFile::new(run)),
+        FileFormat::Lss => file::write_lss(file, run),
+    }
 }
 
-fn write_file(file: &str, run: &Run) -> Result<(), Box<dyn Error>> {
-    let run = RunFile::new(&run);
-    file::write_json(file, run)
+fn modified(path: &str) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{env, fs, process};
+
+    use super::*;
+
+    /// A timer with the splits a, b and c in a new file, `name` keeps the
+    /// files of tests running in parallel apart
+    pub(crate) fn timer(name: &str) -> WlSplitTimer {
+        let file = env::temp_dir().join(format!("wlsplit-{}-{}.json", name, process::id()));
+        fs::remove_file(&file).ok();
+        let metadata = RunMetadata {
+            game_name: None,
+            category_name: None,
+            splits: Some(vec!["a", "b", "c"]),
+        };
+        let mut timer = WlSplitTimer::new(file.to_string_lossy().into_owned(), metadata).unwrap();
+        timer.set_backups(0);
+        timer
+    }
+
+    pub(crate) fn remove(timer: &WlSplitTimer) {
+        fs::remove_file(timer.file()).ok();
+        fs::remove_file(format!("{}.journal", timer.file())).ok();
+    }
+
+    #[test]
+    fn redo_restores_undone_split() {
+        let mut timer = timer("redo");
+        timer.start();
+        timer.split().unwrap();
+        timer.undo();
+        assert_eq!(timer.current_segment_index(), Some(0));
+        timer.redo();
+        assert_eq!(timer.current_segment_index(), Some(1));
+        remove(&timer);
+    }
+
+    #[test]
+    fn redo_does_not_revert_later_changes() {
+        let mut timer = timer("redo-pause");
+        timer.start();
+        timer.split().unwrap();
+        timer.undo();
+        timer.pause();
+        timer.redo();
+        assert_eq!(timer.current_segment_index(), Some(0));
+        assert_eq!(timer.timer().current_phase(), TimerPhase::Paused);
+        remove(&timer);
+    }
+
+    #[test]
+    fn redo_keeps_game_time_and_view_changes() {
+        let mut timer = timer("redo-game-time");
+        timer.start();
+        timer.pause_game_time();
+        timer.set_game_time(TimeSpan::from_seconds(10.0));
+        timer.split().unwrap();
+        timer.undo();
+        timer.set_game_time(TimeSpan::from_seconds(12.0));
+        timer.set_timing_method(TimingMethod::GameTime);
+        timer.set_comparison(best_segments::NAME).unwrap();
+        timer.redo();
+        assert_eq!(timer.current_segment_index(), Some(1));
+        assert_eq!(timer.time(), Some(TimeSpan::from_seconds(12.0)));
+        assert_eq!(timer.comparison(), best_segments::NAME);
+        remove(&timer);
+    }
+
+    #[test]
+    fn undo_skip_checks_the_timing_method() {
+        let mut timer = timer("undo-skip");
+        timer.start();
+        timer.set_timing_method(TimingMethod::GameTime);
+        timer.split().unwrap();
+        timer.undo_skip();
+        assert_eq!(timer.current_segment_index(), Some(1));
+        timer.skip();
+        timer.undo_skip();
+        assert_eq!(timer.current_segment_index(), Some(1));
+        remove(&timer);
+    }
+
+    #[test]
+    fn keeps_the_journal_if_the_file_cant_be_written() {
+        let mut timer = timer("reset-unwritable");
+        let journal = format!("{}.journal", timer.file());
+        timer.start();
+        timer.split().unwrap();
+        // A file can't be renamed over a directory that isn't empty
+        fs::remove_file(timer.file()).unwrap();
+        fs::create_dir_all(Path::new(timer.file()).join("dir")).unwrap();
+
+        assert!(timer.reset(true).is_err());
+        assert!(Path::new(&journal).exists());
+
+        fs::remove_dir_all(timer.file()).unwrap();
+        timer.start();
+        timer.reset(true).unwrap();
+        assert!(!Path::new(&journal).exists());
+        remove(&timer);
+    }
+
+    #[test]
+    fn switches_to_new_files() {
+        let mut timer = timer("create");
+        let file = timer.file().replace("create", "create-new");
+        fs::remove_file(&file).ok();
+        let metadata = || RunMetadata {
+            game_name: Some("Game"),
+            category_name: None,
+            splits: Some(vec!["x", "y"]),
+        };
+        let subscription = timer.subscribe();
+        let previous = timer.file().to_string();
+
+        timer.create(file.clone(), metadata()).unwrap();
+        assert_eq!(timer.file(), file);
+        assert_eq!(timer.game_name(), "Game");
+        assert_eq!(timer.segments().len(), 2);
+        assert!(timer
+            .create(previous.clone(), metadata())
+            .unwrap_err()
+            .ends_with("already exists"));
+
+        timer.start();
+        assert_eq!(subscription.try_recv(), Ok(Event::Started));
+        assert!(timer.open(previous.clone()).is_err());
+        timer.reset(false).unwrap();
+        timer.open(previous).unwrap();
+        assert_eq!(timer.segments().len(), 3);
+        fs::remove_file(file).ok();
+        remove(&timer);
+    }
+
+    #[test]
+    fn switching_keeps_files_edited_elsewhere() {
+        let mut timer = timer("switch-edited");
+        timer.set_backups(DEFAULT_BACKUPS);
+        let previous = timer.file().to_string();
+        let mut other = WlSplitTimer::from_file(previous.clone()).unwrap();
+        other.set_backups(0);
+        let mut run = other.run().clone();
+        run.set_game_name("Edited");
+        other.save_run(run).unwrap();
+
+        let file = previous.replace("switch-edited", "switch-edited-new");
+        fs::remove_file(&file).ok();
+        let metadata = RunMetadata {
+            game_name: None,
+            category_name: None,
+            splits: Some(vec!["x"]),
+        };
+        timer.create(file.clone(), metadata).unwrap();
+        assert_eq!(
+            WlSplitTimer::from_file(previous.clone())
+                .unwrap()
+                .game_name(),
+            "Edited"
+        );
+        assert!(file::list_backups(&previous).is_empty());
+        fs::remove_file(&previous).ok();
+        remove(&timer);
+    }
+
+    #[test]
+    fn idle_reset_keeps_the_backups() {
+        let mut timer = timer("idle-reset");
+        timer.set_backups(DEFAULT_BACKUPS);
+        timer.reset(true).unwrap();
+        assert!(timer.list_backups().is_empty());
+
+        timer.start();
+        timer.reset(true).unwrap();
+        let backups = timer.list_backups();
+        assert_eq!(backups.len(), 1);
+        timer.reset(true).unwrap();
+        assert_eq!(timer.list_backups(), backups);
+        for backup in backups {
+            fs::remove_file(env::temp_dir().join(backup)).ok();
+        }
+        remove(&timer);
+    }
 }
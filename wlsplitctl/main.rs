@@ -1,10 +1,11 @@
-use clap::{App, Arg};
+use clap::{App, AppSettings, Arg};
 use std::env;
 use std::error::Error;
-use std::io::prelude::*;
+use std::io::{prelude::*, BufReader};
 use std::os::unix::net::UnixStream;
 
 const SOCKET_NAME: &str = "wlsplit.sock";
+const PROTOCOL_VERSION: u32 = 1;
 
 fn main() -> Result<(), Box<dyn Error>> {
     let socket_path = format!(
@@ -13,7 +14,15 @@ fn main() -> Result<(), Box<dyn Error>> {
         SOCKET_NAME
     );
     let matches = App::new("wlsplitctl")
-        .arg(Arg::with_name("command").required(true).index(1))
+        // Arguments after the command are passed on, e.g. `new <path> --game <name>`
+        .setting(AppSettings::TrailingVarArg)
+        .arg(
+            Arg::with_name("command")
+                .required(true)
+                .index(1)
+                .multiple(true)
+                .allow_hyphen_values(true),
+        )
         .arg(
             Arg::with_name("socket")
                 .short("s")
@@ -23,12 +32,79 @@ fn main() -> Result<(), Box<dyn Error>> {
         .get_matches();
 
     let socket = matches.value_of("socket").unwrap().to_string();
-    let command = matches
-        .value_of("command")
+    let mut command = matches
+        .values_of("command")
         .expect("Input command required!");
+    let cmd = command.next();
+    let mut args: Vec<String> = command.map(String::from).collect();
+    // wlsplit runs in another directory, so paths are sent as absolute ones
+    if let Some(path) = path_arg(cmd, &args) {
+        args[path] = env::current_dir()?
+            .join(&args[path])
+            .to_string_lossy()
+            .into_owned();
+    }
+    let request = serde_json::json!({
+        "version": PROTOCOL_VERSION,
+        "cmd": cmd,
+        "args": args,
+    });
 
     let mut stream = UnixStream::connect(&socket).expect("Server is not running");
 
-    stream.write_all(&command.as_bytes())?;
+    writeln!(stream, "{}", request)?;
+    let mut reader = BufReader::new(&stream);
+    let mut response = String::new();
+    reader.read_line(&mut response)?;
+    print!("{}", response);
+
+    let response: serde_json::Value = serde_json::from_str(&response)?;
+    if !response["ok"].as_bool().unwrap_or(false) {
+        std::process::exit(1);
+    }
+
+    if cmd == Some("subscribe") {
+        for event in reader.lines() {
+            println!("{}", event?);
+        }
+    }
     Ok(())
 }
+
+/// Index of the argument that is a file path, `new` takes options with values
+/// around it
+fn path_arg(cmd: Option<&str>, args: &[String]) -> Option<usize> {
+    match cmd? {
+        "load" | "export" => (!args.is_empty()).then_some(0),
+        "new" => {
+            let mut index = 0;
+            while args.get(index)?.starts_with("--") {
+                index += 2;
+            }
+            Some(index)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    fn path_arg(cmd: &str, args: &[&str]) -> Option<usize> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        super::path_arg(Some(cmd), &args)
+    }
+
+    #[test]
+    fn finds_path_arguments() {
+        assert_eq!(path_arg("load", &["a.json"]), Some(0));
+        assert_eq!(path_arg("load", &[]), None);
+        assert_eq!(path_arg("export", &["a.lss"]), Some(0));
+        assert_eq!(path_arg("new", &["a.json", "--game", "G"]), Some(0));
+        assert_eq!(
+            path_arg("new", &["--game", "G", "--splits", "a,b", "a.json"]),
+            Some(4)
+        );
+        assert_eq!(path_arg("new", &["--game", "G"]), None);
+        assert_eq!(path_arg("split", &["a.json"]), None);
+    }
+}